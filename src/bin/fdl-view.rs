@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::{cmp, fmt, fs, io};
+use std::{cmp, fmt, io};
 
 use ruscii::app::{App, State};
 use ruscii::drawing::Pencil;
@@ -7,16 +7,8 @@ use ruscii::keyboard::{Key, KeyEvent};
 use ruscii::spatial::Vec2;
 use ruscii::terminal::{Color, Window};
 
-use fdl::core::{ForeachCtrl, Thing};
-use fdl::lexer::Lexer;
-use fdl::parser::{ParseError, Parser};
-
-fn make_err_string(err: &ParseError) -> String {
-    return format!(
-        "line {}:{} - {}",
-        err.token_info.line, err.token_info.col, err.message
-    );
-}
+use fdl::color;
+use fdl::core::{ForeachCtrl, Prop, Thing};
 
 type ThingKey = *const Thing;
 
@@ -66,21 +58,12 @@ impl FdlViewState {
     }
 }
 
-fn parse_file(file_path: String) -> Result<Vec<Thing>, String> {
-    let file_source = match fs::read_to_string(file_path) {
-        Ok(file_source) => file_source,
-        Err(err) => return Err(err.to_string()),
-    };
-
-    let parser = match Parser::from_tokens(Lexer::new(file_source.as_str())) {
-        Ok(parser) => parser,
-        Err(err) => return Err(make_err_string(&err)),
-    };
-
-    return Ok(parser.things.into_values().collect());
+fn parse_file(file_path: String) -> Result<Vec<Thing>, fdl::Error> {
+    let document = fdl::parse_file(file_path)?;
+    return Ok(document.things.into_iter().collect());
 }
 
-fn foreach_thing(things: &Vec<Thing>, mut f: impl FnMut(&Thing, Option<&Thing>, usize) -> ()) {
+fn foreach_thing(things: &Vec<Thing>, mut f: impl FnMut(&Thing, Option<&Thing>, usize)) {
     for thing in things {
         thing.foreach(&mut f);
     }
@@ -95,12 +78,57 @@ fn foreach_thing_ctrl(
     }
 }
 
-fn print_props(thing: &Thing, pencil: &mut Pencil, x_offset: usize) {
-    let mut line = 1;
+fn thing_label(thing: &Thing) -> String {
+    if !thing.name.is_empty() {
+        return thing.name.clone();
+    }
+    return format!("<{}>", thing.kind.as_deref().unwrap_or("thing"));
+}
+
+fn prop_row(name: &str, prop: &Prop) -> String {
+    let mut row = String::new();
+    for annotation in &prop.annotations {
+        row += &format!("{} ", annotation);
+    }
+    return row + &format!("{}: {}", name, prop.value_in_unit());
+}
+
+/// Rows of the props panel: the thing's doc comment followed by each prop with its own. `color`
+/// props come with the color to draw a swatch in.
+fn props_rows(thing: &Thing) -> Vec<(String, Option<color::Color>)> {
+    let doc_rows = |doc: &Option<String>| -> Vec<(String, Option<color::Color>)> {
+        let Some(doc) = doc else {
+            return Vec::new();
+        };
+        return doc
+            .lines()
+            .map(|line| (format!("/// {}", line), None))
+            .collect();
+    };
+
+    let mut rows = doc_rows(&thing.doc);
     for (name, prop) in &thing.props {
-        let text = format!("{}: {}", name, prop.value.to_string());
+        rows.extend(doc_rows(&prop.doc));
+        rows.push((prop_row(name, prop), prop.value.as_color()));
+    }
+    return rows;
+}
+
+/// Closest color of the 6x6x6 cube of the 256 color palette, alpha is ignored.
+fn xterm_color(color: &color::Color) -> Color {
+    let level = |channel: u8| (channel as u16 * 5 + 127) / 255;
+    return Color::Xterm((16 + 36 * level(color.r) + 6 * level(color.g) + level(color.b)) as u8);
+}
+
+fn print_props(thing: &Thing, pencil: &mut Pencil, x_offset: usize) {
+    for (line, (text, swatch)) in (1..).zip(props_rows(thing)) {
         pencil.draw_text(&text, Vec2::xy(x_offset, line + 2));
-        line += 1;
+        if let Some(swatch) = swatch {
+            pencil.set_foreground(xterm_color(&swatch));
+            let x = x_offset + text.chars().count() + 1;
+            pencil.draw_text("██", Vec2::xy(x, line + 2));
+            pencil.set_foreground(Color::White);
+        }
     }
 }
 
@@ -135,6 +163,12 @@ impl From<io::Error> for FdlError {
     }
 }
 
+impl From<fdl::Error> for FdlError {
+    fn from(err: fdl::Error) -> Self {
+        return err.to_string().into();
+    }
+}
+
 fn main() -> Result<(), FdlError> {
     // let file_path = match (env::args().collect::<Vec<String>>()).get(1) {
     //     Some(file_path) => file_path.clone(),
@@ -151,12 +185,12 @@ fn main() -> Result<(), FdlError> {
     foreach_thing(&things, |thing, _, depth| {
         max_height += 1;
 
-        let len: usize = (depth * 4) + 2 + thing.name.len();
+        let len: usize = (depth * 4) + 2 + thing_label(thing).len();
         longest_thing_row = cmp::max(longest_thing_row, len);
 
-        for (name, prop) in &thing.props {
-            let len = name.len() + 2 + prop.value.to_string().len();
-            longest_prop_row = cmp::max(longest_prop_row, len);
+        for (row, swatch) in props_rows(thing) {
+            let swatch_len = if swatch.is_some() { 3 } else { 0 };
+            longest_prop_row = cmp::max(longest_prop_row, row.chars().count() + swatch_len);
         }
     });
 
@@ -195,8 +229,8 @@ fn main() -> Result<(), FdlError> {
                 "›"
             };
             let caret = if thing.num_things() == 0 { "-" } else { caret };
-            let text = format!("{} {}", caret, thing.name);
-            pencil.draw_text(&text, Vec2::xy((depth * 4) + 1, line.clone() + 2));
+            let text = format!("{} {}", caret, thing_label(thing));
+            pencil.draw_text(&text, Vec2::xy((depth * 4) + 1, line + 2));
             line += 1;
 
             pencil.set_background(Color::Black);
@@ -233,4 +267,11 @@ mod tests {
         state.update_index(1, 3);
         assert_eq!(state.select_index, 2);
     }
+
+    #[test]
+    fn xterm_color_picks_the_closest_cube_color() {
+        assert_eq!(xterm_color(&color::Color::rgb(0, 0, 0)).code(), 16);
+        assert_eq!(xterm_color(&color::Color::rgb(255, 255, 255)).code(), 231);
+        assert_eq!(xterm_color(&color::Color::rgb(255, 136, 0)).code(), 214);
+    }
 }
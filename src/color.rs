@@ -0,0 +1,65 @@
+use std::fmt;
+
+/// An sRGB color with alpha, written as `#ff8800`, `#ff880080` or `rgb(255, 136, 0)`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        return Self::rgba(r, g, b, 255);
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        return Self {
+            r: r,
+            g: g,
+            b: b,
+            a: a,
+        };
+    }
+
+    /// Parses `#rrggbb` or `#rrggbbaa`.
+    pub fn from_hex(literal: &str) -> Option<Self> {
+        let digits = literal.strip_prefix('#')?;
+        if (digits.len() != 6 && digits.len() != 8) || !digits.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+        let a = if digits.len() == 8 { channel(6)? } else { 255 };
+        return Some(Self::rgba(channel(0)?, channel(2)?, channel(4)?, a));
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_round_trip_through_hex() {
+        assert_eq!(Color::from_hex("#FF8800"), Some(Color::rgb(255, 136, 0)));
+        assert_eq!(
+            Color::from_hex("#ff880080"),
+            Some(Color::rgba(255, 136, 0, 128))
+        );
+        assert_eq!(Color::rgb(255, 136, 0).to_string(), "#ff8800");
+        assert_eq!(Color::rgba(255, 136, 0, 128).to_string(), "#ff880080");
+        assert_eq!(Color::from_hex("#ff88"), None);
+        assert_eq!(Color::from_hex("#ff88zz"), None);
+    }
+}
@@ -1,27 +1,120 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
 
-use crate::string_utils::strip_quotes;
+use crate::color::Color;
+use crate::lexer::TokenInfo;
+use crate::string_utils::{encode_hex, strip_quotes};
+use crate::time::{format_duration, Date, DateTime};
+use crate::units::Unit;
+use crate::vector::{Vec2, Vec3, Vec4};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
+pub enum PropType {
+    Int,
+    Long,
+    UInt,
+    Float,
+    Double,
+    Bool,
+    String,
+    Enum(String),
+    Ref,
+    Duration,
+    Date,
+    DateTime,
+    Bytes,
+    Color,
+    Vec2,
+    Vec3,
+    Vec4,
+    /// A type registered with `ParserBuilder::prop_type`.
+    Custom(String),
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum PropValue {
     Int(i32),
+    Long(i64),
+    UInt(u64),
     Float(f32),
+    Double(f64),
     Bool(bool),
     String(String),
+    Enum { ty: String, variant: String },
+    Ref(String),
+    Duration(Duration),
+    Date(Date),
+    DateTime(DateTime),
+    Bytes(Vec<u8>),
+    Color(Color),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Custom { ty: String, fields: Vec<PropValue> },
     Err,
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<String>,
+}
+
+/// Metadata attached to a thing or prop with `@name(args...)`, e.g. `@range(0, 100)`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Annotation {
+    pub name: String,
+    pub args: Vec<PropValue>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Prop {
     pub name: String,
     pub value: PropValue,
+    pub annotations: Vec<Annotation>,
+    /// Lines of the `///` comments above the declaration.
+    pub doc: Option<String>,
+    /// Unit suffix the value was written with, e.g. `ms`. The value itself is converted to the
+    /// canonical unit of the dimension, see [`Prop::value_in_unit`].
+    pub unit: Option<String>,
+    /// Layer the prop was read from when several files are merged by a `Loader`.
+    pub origin: Option<String>,
+    /// The string as declared, for values with `${...}` placeholders.
+    pub(crate) template: Option<Rc<Template>>,
+    /// Where the value was written, for checks that run again after the tree changed.
+    pub(crate) token_info: Option<TokenInfo>,
 }
 
+/// A string prop as declared with `${...}` placeholders. Kept with the substituted value so the
+/// placeholders can be substituted again when the tree around the prop changes, e.g. once later
+/// layers are merged into it.
 #[derive(Debug)]
+pub(crate) struct Template {
+    pub(crate) source: String,
+    /// Constants visible at the declaration.
+    pub(crate) consts: HashMap<String, PropValue>,
+    pub(crate) token_info: TokenInfo,
+}
+
+#[derive(Debug, Clone)]
 pub struct Thing {
     pub name: String,
+    /// Block keyword or `thing:Kind` annotation the thing was declared with, `None` for `thing`.
+    pub kind: Option<String>,
     pub props: HashMap<String, Prop>,
-    things: HashMap<String, Thing>,
+    pub(crate) things: Things,
+    pub annotations: Vec<Annotation>,
+    /// Doc comment of the thing, like [`Prop::doc`].
+    pub doc: Option<String>,
+}
+
+/// Children of a thing in declaration order. Names may repeat or be empty (anonymous things),
+/// lookups by name return the first match.
+#[derive(Debug, Clone, Default)]
+pub struct Things {
+    things: Vec<Thing>,
 }
 
 pub struct ThingBuilder {
@@ -34,61 +127,351 @@ pub enum ForeachCtrl {
     Continue,
 }
 
+impl PropType {
+    /// Maps a built-in type keyword (including its aliases) to its type.
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        return match keyword {
+            "int" => Some(PropType::Int),
+            "long" | "i64" => Some(PropType::Long),
+            "uint" | "u64" => Some(PropType::UInt),
+            "float" => Some(PropType::Float),
+            "double" | "f64" => Some(PropType::Double),
+            "bool" => Some(PropType::Bool),
+            "string" => Some(PropType::String),
+            "ref" => Some(PropType::Ref),
+            "duration" => Some(PropType::Duration),
+            "date" => Some(PropType::Date),
+            "datetime" => Some(PropType::DateTime),
+            "bytes" => Some(PropType::Bytes),
+            "color" => Some(PropType::Color),
+            "vec2" => Some(PropType::Vec2),
+            "vec3" => Some(PropType::Vec3),
+            "vec4" => Some(PropType::Vec4),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for PropType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            PropType::Int => f.write_str("int"),
+            PropType::Long => f.write_str("long"),
+            PropType::UInt => f.write_str("uint"),
+            PropType::Float => f.write_str("float"),
+            PropType::Double => f.write_str("double"),
+            PropType::Bool => f.write_str("bool"),
+            PropType::String => f.write_str("string"),
+            PropType::Enum(name) => f.write_str(name),
+            PropType::Ref => f.write_str("ref"),
+            PropType::Duration => f.write_str("duration"),
+            PropType::Date => f.write_str("date"),
+            PropType::DateTime => f.write_str("datetime"),
+            PropType::Bytes => f.write_str("bytes"),
+            PropType::Color => f.write_str("color"),
+            PropType::Vec2 => f.write_str("vec2"),
+            PropType::Vec3 => f.write_str("vec3"),
+            PropType::Vec4 => f.write_str("vec4"),
+            PropType::Custom(name) => f.write_str(name),
+        };
+    }
+}
+
 impl PropValue {
-    pub fn to_string(&self) -> String {
+    pub fn prop_type(&self) -> Option<PropType> {
+        return match self {
+            PropValue::Int(_) => Some(PropType::Int),
+            PropValue::Long(_) => Some(PropType::Long),
+            PropValue::UInt(_) => Some(PropType::UInt),
+            PropValue::Float(_) => Some(PropType::Float),
+            PropValue::Double(_) => Some(PropType::Double),
+            PropValue::Bool(_) => Some(PropType::Bool),
+            PropValue::String(_) => Some(PropType::String),
+            PropValue::Enum { ty, .. } => Some(PropType::Enum(ty.clone())),
+            PropValue::Ref(_) => Some(PropType::Ref),
+            PropValue::Duration(_) => Some(PropType::Duration),
+            PropValue::Date(_) => Some(PropType::Date),
+            PropValue::DateTime(_) => Some(PropType::DateTime),
+            PropValue::Bytes(_) => Some(PropType::Bytes),
+            PropValue::Color(_) => Some(PropType::Color),
+            PropValue::Vec2(_) => Some(PropType::Vec2),
+            PropValue::Vec3(_) => Some(PropType::Vec3),
+            PropValue::Vec4(_) => Some(PropType::Vec4),
+            PropValue::Custom { ty, .. } => Some(PropType::Custom(ty.clone())),
+            PropValue::Err => None,
+        };
+    }
+
+    pub fn as_color(&self) -> Option<Color> {
+        return match self {
+            PropValue::Color(val) => Some(*val),
+            _ => None,
+        };
+    }
+
+    pub fn as_vec2(&self) -> Option<Vec2> {
         return match self {
-            PropValue::Int(val) => val.to_string(),
-            PropValue::Float(val) => val.to_string(),
-            PropValue::Bool(val) => val.to_string(),
-            PropValue::String(val) => val.clone(),
-            PropValue::Err => "Err".to_string(),
+            PropValue::Vec2(val) => Some(*val),
+            _ => None,
+        };
+    }
+
+    pub fn as_vec3(&self) -> Option<Vec3> {
+        return match self {
+            PropValue::Vec3(val) => Some(*val),
+            _ => None,
+        };
+    }
+
+    pub fn as_vec4(&self) -> Option<Vec4> {
+        return match self {
+            PropValue::Vec4(val) => Some(*val),
+            _ => None,
+        };
+    }
+
+    fn as_integer(&self) -> Option<i128> {
+        return match self {
+            PropValue::Int(val) => Some(*val as i128),
+            PropValue::Long(val) => Some(*val as i128),
+            PropValue::UInt(val) => Some(*val as i128),
+            _ => None,
+        };
+    }
+
+    /// Converts the value to `ty` when that can be done without losing information, e.g. an `int`
+    /// into a `double` or a non-negative `long` into a `uint`.
+    pub fn convert(&self, ty: &PropType) -> Option<PropValue> {
+        if self.prop_type().as_ref() == Some(ty) {
+            return Some(self.clone());
+        }
+
+        if let Some(val) = self.as_integer() {
+            return match ty {
+                PropType::Int => i32::try_from(val).ok().map(PropValue::Int),
+                PropType::Long => i64::try_from(val).ok().map(PropValue::Long),
+                PropType::UInt => u64::try_from(val).ok().map(PropValue::UInt),
+                PropType::Float if (val as f32) as i128 == val => {
+                    Some(PropValue::Float(val as f32))
+                }
+                PropType::Double if (val as f64) as i128 == val => {
+                    Some(PropValue::Double(val as f64))
+                }
+                _ => None,
+            };
+        }
+
+        return match (self, ty) {
+            (PropValue::Float(val), PropType::Double) => Some(PropValue::Double(*val as f64)),
+            (PropValue::Double(val), PropType::Float) if (*val as f32) as f64 == *val => {
+                Some(PropValue::Float(*val as f32))
+            }
+            _ => None,
         };
     }
 }
 
+impl fmt::Display for PropValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            PropValue::Int(val) => write!(f, "{}", val),
+            PropValue::Long(val) => write!(f, "{}", val),
+            PropValue::UInt(val) => write!(f, "{}", val),
+            PropValue::Float(val) => write!(f, "{}", val),
+            PropValue::Double(val) => write!(f, "{}", val),
+            PropValue::Bool(val) => write!(f, "{}", val),
+            PropValue::String(val) => f.write_str(val),
+            PropValue::Enum { variant, .. } => f.write_str(variant),
+            PropValue::Ref(path) => write!(f, "@\"{}\"", path),
+            PropValue::Duration(val) => f.write_str(&format_duration(val)),
+            PropValue::Date(val) => write!(f, "{}", val),
+            PropValue::DateTime(val) => write!(f, "{}", val),
+            PropValue::Bytes(val) => write!(f, "x\"{}\"", encode_hex(val)),
+            PropValue::Color(val) => write!(f, "{}", val),
+            PropValue::Vec2(val) => write!(f, "{}", val),
+            PropValue::Vec3(val) => write!(f, "{}", val),
+            PropValue::Vec4(val) => write!(f, "{}", val),
+            PropValue::Custom { ty, fields } => {
+                let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+                write!(f, "{}({})", ty, fields.join(", "))
+            }
+            PropValue::Err => f.write_str("Err"),
+        };
+    }
+}
+
+impl EnumDef {
+    pub fn new(name: impl Into<String>) -> Self {
+        return Self {
+            name: name.into(),
+            variants: Vec::new(),
+        };
+    }
+
+    pub fn has_variant(&self, variant: &str) -> bool {
+        return self.variants.iter().any(|v| v == variant);
+    }
+}
+
+impl Annotation {
+    pub fn new(name: impl Into<String>, args: Vec<PropValue>) -> Self {
+        return Self {
+            name: name.into(),
+            args: args,
+        };
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if self.args.is_empty() {
+            return Ok(());
+        }
+
+        let args: Vec<String> = (self.args.iter())
+            .map(|arg| match arg {
+                PropValue::String(val) => format!("\"{}\"", val),
+                other => other.to_string(),
+            })
+            .collect();
+        return write!(f, "({})", args.join(", "));
+    }
+}
+
 impl Prop {
-    pub fn new_err(name: impl Into<String>) -> Self {
+    pub fn new(name: impl Into<String>, value: PropValue) -> Self {
         return Self {
             name: name.into(),
-            value: PropValue::Err,
+            value: value,
+            annotations: Vec::new(),
+            doc: None,
+            unit: None,
+            origin: None,
+            template: None,
+            token_info: None,
         };
     }
 
+    /// The value as written, converted back from the canonical unit to `unit`, e.g. `250ms`.
+    /// Picks the shortest number that converts to the stored value, usually the literal itself.
+    pub fn value_in_unit(&self) -> String {
+        let Some(unit) = self.unit.as_deref().and_then(Unit::lookup) else {
+            return self.value.to_string();
+        };
+        let canonical = match self.value {
+            PropValue::Int(val) => val as f64,
+            PropValue::Long(val) => val as f64,
+            PropValue::UInt(val) => val as f64,
+            PropValue::Float(val) => val as f64,
+            PropValue::Double(val) => val,
+            _ => return self.value.to_string(),
+        };
+
+        // `float` props only hold the canonical value with `f32` precision
+        let stores_as = |val: f64| match self.value {
+            PropValue::Float(stored) => unit.to_canonical(val) as f32 == stored,
+            _ => unit.to_canonical(val) == canonical,
+        };
+        let value = unit.from_canonical(canonical);
+        let literal = (0..=17)
+            .map(|digits| format!("{:.*}", digits, value))
+            .find(|literal| literal.parse().is_ok_and(stores_as))
+            .unwrap_or_else(|| value.to_string());
+        return literal + unit.name;
+    }
+
+    pub fn new_err(name: impl Into<String>) -> Self {
+        return Self::new(name, PropValue::Err);
+    }
+
+    pub fn annotation(&self, name: &str) -> Option<&Annotation> {
+        return self
+            .annotations
+            .iter()
+            .find(|annotation| annotation.name == name);
+    }
+
     pub fn int_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
         match literal.into().parse::<i32>() {
-            Ok(val) => Self {
-                name: name.into(),
-                value: PropValue::Int(val),
-            },
+            Ok(val) => Self::new(name, PropValue::Int(val)),
+            Err(_) => Self::new_err(name),
+        }
+    }
+
+    pub fn long_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
+        match literal.into().parse::<i64>() {
+            Ok(val) => Self::new(name, PropValue::Long(val)),
+            Err(_) => Self::new_err(name),
+        }
+    }
+
+    pub fn uint_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
+        match literal.into().parse::<u64>() {
+            Ok(val) => Self::new(name, PropValue::UInt(val)),
             Err(_) => Self::new_err(name),
         }
     }
 
     pub fn float_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
+        // literals too large for the type parse to infinity rather than failing
         match literal.into().parse::<f32>() {
-            Ok(val) => Self {
-                name: name.into(),
-                value: PropValue::Float(val),
-            },
-            Err(_) => Self::new_err(name),
+            Ok(val) if val.is_finite() => Self::new(name, PropValue::Float(val)),
+            _ => Self::new_err(name),
+        }
+    }
+
+    pub fn double_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
+        match literal.into().parse::<f64>() {
+            Ok(val) if val.is_finite() => Self::new(name, PropValue::Double(val)),
+            _ => Self::new_err(name),
         }
     }
 
     pub fn bool_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
         match literal.into().parse::<bool>() {
-            Ok(val) => Self {
-                name: name.into(),
-                value: PropValue::Bool(val),
-            },
+            Ok(val) => Self::new(name, PropValue::Bool(val)),
             Err(_) => Self::new_err(name),
         }
     }
 
     pub fn string_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
-        return Self {
-            name: name.into(),
-            value: PropValue::String(strip_quotes(literal.into().as_str()).to_string()),
+        return Self::new(
+            name,
+            PropValue::String(strip_quotes(literal.into().as_str()).to_string()),
+        );
+    }
+
+    /// Parses a reference literal of the form `@"Path/To/Thing"`.
+    pub fn ref_from_literal(name: impl Into<String>, literal: impl Into<String>) -> Self {
+        let literal = literal.into();
+        let Some(quoted) = literal.strip_prefix('@') else {
+            return Self::new_err(name);
         };
+
+        let path = strip_quotes(quoted);
+        if path.is_empty() || !quoted.starts_with('"') {
+            return Self::new_err(name);
+        }
+
+        return Self::new(name, PropValue::Ref(path.to_string()));
+    }
+
+    pub fn enum_from_literal(
+        name: impl Into<String>,
+        def: &EnumDef,
+        literal: impl Into<String>,
+    ) -> Self {
+        let literal = literal.into();
+        if !def.has_variant(&literal) {
+            return Self::new_err(name);
+        }
+
+        let value = PropValue::Enum {
+            ty: def.name.clone(),
+            variant: literal,
+        };
+        return Self::new(name, value);
     }
 }
 
@@ -96,8 +479,11 @@ impl Thing {
     pub fn new(name: impl Into<String>) -> Self {
         return Thing {
             name: name.into(),
+            kind: None,
             props: HashMap::new(),
-            things: HashMap::new(),
+            things: Things::new(),
+            annotations: Vec::new(),
+            doc: None,
         };
     }
 
@@ -107,16 +493,67 @@ impl Thing {
         };
     }
 
+    pub fn is_kind(&self, kind: &str) -> bool {
+        return self.kind.as_deref() == Some(kind);
+    }
+
+    pub fn annotation(&self, name: &str) -> Option<&Annotation> {
+        return self
+            .annotations
+            .iter()
+            .find(|annotation| annotation.name == name);
+    }
+
+    /// Appends this thing and all its descendants of the given kind to `found`.
+    pub fn collect_kind<'a>(&'a self, kind: &str, found: &mut Vec<&'a Thing>) {
+        if self.is_kind(kind) {
+            found.push(self);
+        }
+        for child in &self.things {
+            child.collect_kind(kind, found);
+        }
+    }
+
     pub fn add_prop(&mut self, prop: Prop) {
         self.props.insert(prop.name.clone(), prop);
     }
 
+    /// The `color` prop `name`, `None` if it is missing or of another type.
+    pub fn get_color(&self, name: &str) -> Option<Color> {
+        return self.props.get(name)?.value.as_color();
+    }
+
+    pub fn get_vec2(&self, name: &str) -> Option<Vec2> {
+        return self.props.get(name)?.value.as_vec2();
+    }
+
+    pub fn get_vec3(&self, name: &str) -> Option<Vec3> {
+        return self.props.get(name)?.value.as_vec3();
+    }
+
+    pub fn get_vec4(&self, name: &str) -> Option<Vec4> {
+        return self.props.get(name)?.value.as_vec4();
+    }
+
     pub fn num_things(&self) -> usize {
         return self.things.len();
     }
 
-    pub fn add_thing(&mut self, thing: Thing) -> Option<Thing> {
-        return self.things.insert(thing.name.clone(), thing);
+    /// The children in declaration order, including repeated and anonymous ones.
+    pub fn things(&self) -> &Things {
+        return &self.things;
+    }
+
+    pub fn things_mut(&mut self) -> &mut Things {
+        return &mut self.things;
+    }
+
+    pub fn add_thing(&mut self, thing: Thing) {
+        self.things.push(thing);
+    }
+
+    pub fn remove_thing(&mut self, key: impl Into<String>) -> Option<Thing> {
+        return self.things.remove(&key.into());
     }
 
     pub fn get_thing(&self, key: impl Into<String>) -> Option<&Thing> {
@@ -127,19 +564,28 @@ impl Thing {
         return self.things.get_mut(&key.into());
     }
 
+    pub fn get_things(&self, key: impl Into<String>) -> Vec<&Thing> {
+        let key = key.into();
+        return self
+            .things
+            .iter()
+            .filter(|thing| thing.name == key)
+            .collect();
+    }
+
     fn foreach_helper(
         thing: &Thing,
         parent: Option<&Thing>,
         depth: usize,
-        f: &mut impl FnMut(&Thing, Option<&Thing>, usize) -> (),
+        f: &mut impl FnMut(&Thing, Option<&Thing>, usize),
     ) {
         f(thing, parent, depth);
-        for (_, child) in &thing.things {
+        for child in &thing.things {
             Self::foreach_helper(child, Some(thing), depth + 1, f);
         }
     }
 
-    pub fn foreach(&self, mut f: impl FnMut(&Thing, Option<&Thing>, usize) -> ()) {
+    pub fn foreach(&self, mut f: impl FnMut(&Thing, Option<&Thing>, usize)) {
         Self::foreach_helper(self, None, 0, &mut f);
     }
 
@@ -155,10 +601,10 @@ impl Thing {
             _ => {}
         };
 
-        for (_, child) in &thing.things {
-            match Self::foreach_ctrl_helper(child, Some(thing), depth + 1, f) {
-                ForeachCtrl::Break => return ForeachCtrl::Break,
-                _ => {}
+        for child in &thing.things {
+            if let ForeachCtrl::Break = Self::foreach_ctrl_helper(child, Some(thing), depth + 1, f)
+            {
+                return ForeachCtrl::Break;
             }
         }
         return ForeachCtrl::Continue;
@@ -169,7 +615,105 @@ impl Thing {
     }
 }
 
+impl Things {
+    pub fn new() -> Self {
+        return Self { things: Vec::new() };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.things.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.things.is_empty();
+    }
+
+    pub fn push(&mut self, thing: Thing) {
+        self.things.push(thing);
+    }
+
+    /// Replaces the first thing with the same name in place, or appends it if there is none.
+    pub fn insert(&mut self, thing: Thing) -> Option<Thing> {
+        return match self
+            .things
+            .iter_mut()
+            .find(|other| other.name == thing.name)
+        {
+            Some(existing) => Some(std::mem::replace(existing, thing)),
+            None => {
+                self.things.push(thing);
+                None
+            }
+        };
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Thing> {
+        return self.things.iter().find(|thing| thing.name == name);
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Thing> {
+        return self.things.iter_mut().find(|thing| thing.name == name);
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Thing> {
+        return self.things.iter().filter(move |thing| thing.name == name);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        return self.get(name).is_some();
+    }
+
+    /// Removes the first thing with the given name.
+    pub fn remove(&mut self, name: &str) -> Option<Thing> {
+        let index = self.things.iter().position(|thing| thing.name == name)?;
+        return Some(self.things.remove(index));
+    }
+
+    pub fn at(&self, index: usize) -> Option<&Thing> {
+        return self.things.get(index);
+    }
+
+    pub fn at_mut(&mut self, index: usize) -> Option<&mut Thing> {
+        return self.things.get_mut(index);
+    }
+
+    pub fn remove_at(&mut self, index: usize) -> Thing {
+        return self.things.remove(index);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Thing> {
+        return self.things.iter();
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Thing> {
+        return self.things.iter_mut();
+    }
+}
+
+impl IntoIterator for Things {
+    type Item = Thing;
+    type IntoIter = std::vec::IntoIter<Thing>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.things.into_iter();
+    }
+}
+
+impl<'a> IntoIterator for &'a Things {
+    type Item = &'a Thing;
+    type IntoIter = std::slice::Iter<'a, Thing>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.things.iter();
+    }
+}
+
 impl ThingBuilder {
+    pub fn kind(mut self, kind: impl Into<String>) -> ThingBuilder {
+        self.thing.kind = Some(kind.into());
+        return self;
+    }
+
     pub fn thing(mut self, thing: Thing) -> ThingBuilder {
         self.thing.add_thing(thing);
         return self;
@@ -182,6 +726,11 @@ impl ThingBuilder {
         return self;
     }
 
+    pub fn annotation(mut self, annotation: Annotation) -> ThingBuilder {
+        self.thing.annotations.push(annotation);
+        return self;
+    }
+
     pub fn prop(mut self, prop: Prop) -> ThingBuilder {
         self.thing.add_prop(prop);
         return self;
@@ -208,6 +757,53 @@ mod tests {
         assert_eq!(prop.value, PropValue::Float(12.1));
     }
 
+    #[test]
+    fn prop_can_parse_64_bit_and_unsigned_numbers() {
+        let long_prop = Prop::long_from_literal("name", "-9007199254740993");
+        let uint_prop = Prop::uint_from_literal("name", "18446744073709551615");
+        let double_prop = Prop::double_from_literal("name", "9000.01");
+        assert_eq!(long_prop.value, PropValue::Long(-9007199254740993));
+        assert_eq!(uint_prop.value, PropValue::UInt(u64::MAX));
+        assert_eq!(double_prop.value, PropValue::Double(9000.01));
+    }
+
+    #[test]
+    fn prop_rejects_out_of_range_numbers() {
+        assert_eq!(
+            Prop::int_from_literal("name", "2147483648").value,
+            PropValue::Err
+        );
+        assert_eq!(Prop::uint_from_literal("name", "-1").value, PropValue::Err);
+        assert_eq!(
+            Prop::long_from_literal("name", "9223372036854775808").value,
+            PropValue::Err
+        );
+        assert_eq!(
+            Prop::float_from_literal("name", "9".repeat(40)).value,
+            PropValue::Err
+        );
+    }
+
+    #[test]
+    fn numeric_prop_values_round_trip_through_strings() {
+        for value in [
+            PropValue::Long(i64::MIN),
+            PropValue::UInt(u64::MAX),
+            PropValue::Double(9000.01),
+            PropValue::Double(0.1 + 0.2),
+            PropValue::Float(9000.01),
+        ] {
+            let literal = value.to_string();
+            let parsed = match value {
+                PropValue::Long(_) => Prop::long_from_literal("name", literal),
+                PropValue::UInt(_) => Prop::uint_from_literal("name", literal),
+                PropValue::Double(_) => Prop::double_from_literal("name", literal),
+                _ => Prop::float_from_literal("name", literal),
+            };
+            assert_eq!(parsed.value, value);
+        }
+    }
+
     #[test]
     fn prop_can_parse_bool() {
         let prop = Prop::bool_from_literal("name", "true");
@@ -220,6 +816,60 @@ mod tests {
         assert_eq!(prop.value, PropValue::String("hello world".to_string()));
     }
 
+    #[test]
+    fn prop_can_parse_enum_variant() {
+        let mut def = EnumDef::new("LogLevel");
+        def.variants = vec!["Debug".to_string(), "Info".to_string()];
+        let prop = Prop::enum_from_literal("level", &def, "Info");
+        assert_eq!(
+            prop.value,
+            PropValue::Enum {
+                ty: "LogLevel".to_string(),
+                variant: "Info".to_string()
+            }
+        );
+        assert_eq!(
+            Prop::enum_from_literal("level", &def, "Warn").value,
+            PropValue::Err
+        );
+    }
+
+    #[test]
+    fn prop_can_parse_ref() {
+        let prop = Prop::ref_from_literal("name", "@\"Hello/World\"");
+        assert_eq!(prop.value, PropValue::Ref("Hello/World".to_string()));
+        assert_eq!(prop.value.to_string(), "@\"Hello/World\"");
+        assert_eq!(
+            Prop::ref_from_literal("name", "\"Hello\"").value,
+            PropValue::Err
+        );
+        assert_eq!(
+            Prop::ref_from_literal("name", "@\"\"").value,
+            PropValue::Err
+        );
+    }
+
+    #[test]
+    fn prop_values_convert_losslessly_between_numeric_types() {
+        assert_eq!(
+            PropValue::Int(32).convert(&PropType::Double),
+            Some(PropValue::Double(32.0))
+        );
+        assert_eq!(
+            PropValue::Long(7).convert(&PropType::UInt),
+            Some(PropValue::UInt(7))
+        );
+        assert_eq!(
+            PropValue::Float(0.5).convert(&PropType::Double),
+            Some(PropValue::Double(0.5))
+        );
+        assert_eq!(PropValue::Long(-1).convert(&PropType::UInt), None);
+        assert_eq!(PropValue::UInt(u64::MAX).convert(&PropType::Int), None);
+        assert_eq!(PropValue::Double(0.1).convert(&PropType::Float), None);
+        assert_eq!(PropValue::Float(1.0).convert(&PropType::Int), None);
+        assert_eq!(PropValue::Bool(true).convert(&PropType::String), None);
+    }
+
     #[test]
     fn prop_recover_from_parse_errors_and_produce_err_props() {
         let int_prop = Prop::int_from_literal("name", "hello world");
@@ -236,6 +886,31 @@ mod tests {
         assert_eq!(thing.name, "thing");
     }
 
+    #[test]
+    fn things_can_have_a_kind() {
+        let thing = Thing::build("Goblin").kind("enemy").finish();
+        assert!(thing.is_kind("enemy"));
+        assert!(!thing.is_kind("item"));
+        assert!(!Thing::new("Plain").is_kind("enemy"));
+    }
+
+    #[test]
+    fn annotations_display_with_their_args() {
+        let thing = Thing::build("Config")
+            .annotation(Annotation::new("secret", vec![]))
+            .annotation(Annotation::new(
+                "range",
+                vec![PropValue::Int(0), PropValue::String("max".to_string())],
+            ))
+            .finish();
+        assert_eq!(thing.annotation("secret").unwrap().to_string(), "@secret");
+        assert_eq!(
+            thing.annotation("range").unwrap().to_string(),
+            "@range(0, \"max\")"
+        );
+        assert!(thing.annotation("unit").is_none());
+    }
+
     #[test]
     fn props_can_be_added_to_things() {
         let mut thing = Thing::new("thing");
@@ -255,6 +930,49 @@ mod tests {
         assert_eq!(thing.get_thing("thing2").unwrap().name, "thing2");
     }
 
+    #[test]
+    fn things_can_be_removed_from_things() {
+        let mut thing = Thing::build("thing").thing(Thing::new("thing2")).finish();
+        assert_eq!(thing.remove_thing("thing2").unwrap().name, "thing2");
+        assert!(thing.remove_thing("thing2").is_none());
+        assert_eq!(thing.num_things(), 0);
+    }
+
+    #[test]
+    fn things_keep_order_and_allow_duplicate_names() {
+        let mut thing = Thing::build("Path")
+            .things(vec![
+                Thing::build("Point")
+                    .prop(Prop::int_from_literal("x", "1"))
+                    .finish(),
+                Thing::new(""),
+                Thing::build("Point")
+                    .prop(Prop::int_from_literal("x", "2"))
+                    .finish(),
+            ])
+            .finish();
+        assert_eq!(thing.num_things(), 3);
+
+        let names: Vec<&str> = thing.things.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["Point", "", "Point"]);
+        assert_eq!(thing.get_things("Point").len(), 2);
+        assert_eq!(
+            thing
+                .get_thing("Point")
+                .unwrap()
+                .props
+                .get("x")
+                .unwrap()
+                .value,
+            PropValue::Int(1)
+        );
+
+        let replaced = thing.things.insert(Thing::new("Point")).unwrap();
+        assert_eq!(replaced.props.get("x").unwrap().value, PropValue::Int(1));
+        assert_eq!(thing.things.at(0).unwrap().props.len(), 0);
+        assert_eq!(thing.num_things(), 3);
+    }
+
     #[test]
     fn foreach_thing_traverses_thing_trees() {
         let thing = Thing::build("Hello")
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::{
+    core::Things,
+    lexer::Lexer,
+    parser::{Diagnostic, ParseError, Parser, ParserBuilder},
+};
+
+/// A parsed fdl file together with the text it was parsed from.
+#[derive(Debug)]
+pub struct Document {
+    pub things: Things,
+    pub source: String,
+    /// Path the source was read from, `None` for sources that don't come from a file.
+    pub file_name: Option<String>,
+    /// Problems that didn't stop parsing.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(ParseError),
+    /// An error in one of the files combined by a [`Loader`](crate::loader::Loader).
+    Layer(String, Box<Error>),
+}
+
+impl Document {
+    pub(crate) fn parse(source: String, file_name: Option<String>) -> Result<Self, Error> {
+        let (document, _) = Self::parse_over(Parser::new(), Parser::build(), source, file_name)?;
+        return Ok(document);
+    }
+
+    /// Parses `source` with `builder` on top of an `earlier` parse, whose things its `patch`
+    /// blocks can change and whose enums, constants and templates it can use. Returns the
+    /// document holding only the things declared in `source`, and the parser holding the earlier
+    /// things as patched.
+    pub(crate) fn parse_over(
+        earlier: Parser,
+        builder: ParserBuilder,
+        source: String,
+        file_name: Option<String>,
+    ) -> Result<(Self, Parser), Error> {
+        let num_earlier = earlier.things.len();
+        let mut parser = builder.after(earlier).parse(Lexer::new(&source))?;
+
+        let mut earlier = Things::new();
+        let mut things = Things::new();
+        for (index, thing) in std::mem::take(&mut parser.things).into_iter().enumerate() {
+            if index < num_earlier {
+                earlier.push(thing);
+            } else {
+                things.push(thing);
+            }
+        }
+
+        let document = Self {
+            things: things,
+            source: source,
+            file_name: file_name,
+            diagnostics: std::mem::take(&mut parser.diagnostics),
+        };
+        parser.things = earlier;
+        return Ok((document, parser));
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::Io(err) => err.fmt(f),
+            Error::Parse(err) => err.fmt(f),
+            Error::Layer(name, err) => write!(f, "{}: {}", name, err),
+        };
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::Layer(_, err) => Some(err.as_ref()),
+        };
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        return Error::Io(err);
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        return Error::Parse(err);
+    }
+}
+
+pub fn parse_str(source: &str) -> Result<Document, Error> {
+    return Document::parse(source.to_string(), None);
+}
+
+pub fn parse_file(path: impl AsRef<Path>) -> Result<Document, Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    return Document::parse(source, Some(path.display().to_string()));
+}
+
+/// Reads the whole source before parsing, it has to be valid UTF-8.
+pub fn parse_reader(mut reader: impl Read) -> Result<Document, Error> {
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    return Document::parse(source, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::PropValue;
+
+    #[test]
+    fn parse_str_returns_things_and_source() {
+        let source = r#"thing "A" { x = 1 }"#;
+        let document = parse_str(source).unwrap();
+        assert_eq!(document.source, source);
+        assert_eq!(document.file_name, None);
+        assert_eq!(
+            document.things.get("A").unwrap().props["x"].value,
+            PropValue::Int(1)
+        );
+    }
+
+    #[test]
+    fn parse_reader_reads_the_whole_source() {
+        let document = parse_reader(r#"thing "A" { } thing "B" { }"#.as_bytes()).unwrap();
+        assert_eq!(document.things.len(), 2);
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let err = parse_file("does/not/exist.fdl").unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+    }
+
+    #[test]
+    fn parse_errors_keep_their_location() {
+        let err = parse_str(r#"thing "A" { x = }"#).unwrap_err();
+        let Error::Parse(parse_err) = &err else {
+            panic!("expected a parse error, got {:?}", err);
+        };
+        assert_eq!(parse_err.token_info.col, 16);
+        assert_eq!(
+            err.to_string(),
+            "line 0:16 - ".to_owned() + &parse_err.message
+        );
+    }
+
+    #[test]
+    fn stray_doc_comments_are_reported() {
+        let document = parse_str("thing \"A\" {\n  /// nothing here\n}").unwrap();
+        assert_eq!(document.diagnostics.len(), 1);
+        assert_eq!(document.diagnostics[0].token_info.line, 1);
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Source of the variables read by `env(...)` prop values.
+pub trait Environment: fmt::Debug {
+    fn var(&self, name: &str) -> Option<String>;
+}
+
+/// Reads variables from the process environment.
+#[derive(Debug, Default)]
+pub struct StdEnvironment;
+
+impl Environment for StdEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        return std::env::var(name).ok();
+    }
+}
+
+impl Environment for HashMap<String, String> {
+    fn var(&self, name: &str) -> Option<String> {
+        return self.get(name).cloned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_environment_returns_its_entries() {
+        let env = HashMap::from([("PORT".to_string(), "8080".to_string())]);
+        assert_eq!(env.var("PORT"), Some("8080".to_string()));
+        assert_eq!(env.var("HOST"), None);
+    }
+
+    #[test]
+    fn std_environment_reads_process_variables() {
+        // only this test touches the variable, so setting it can't race with other tests
+        std::env::set_var("FDL_ENV_TEST_VARIABLE", "42");
+        assert_eq!(
+            StdEnvironment.var("FDL_ENV_TEST_VARIABLE"),
+            Some("42".to_string())
+        );
+        assert_eq!(StdEnvironment.var("FDL_SURELY_UNSET_VARIABLE"), None);
+    }
+}
@@ -0,0 +1,486 @@
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use crate::core::{PropType, PropValue};
+use crate::time::{parse_duration, Date, DateTime};
+use crate::units::{Dimension, Unit};
+
+pub const TYPE_MISMATCH: &str = "Unable to parse prop value matching declared prop type";
+
+/// Intermediate result of folding a prop value expression. Numbers are kept at full precision
+/// until the final value is narrowed to the declared prop type.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    /// A number with a unit suffix, `value` is converted to the canonical unit of its dimension.
+    Quantity {
+        value: f64,
+        unit: &'static Unit,
+    },
+    /// Values that don't take part in arithmetic, like enum variants and refs.
+    Prop(PropValue),
+}
+
+impl Value {
+    pub fn from_prop_value(value: &PropValue) -> Self {
+        return match value {
+            PropValue::Int(val) => Value::Int(*val as i128),
+            PropValue::Long(val) => Value::Int(*val as i128),
+            PropValue::UInt(val) => Value::Int(*val as i128),
+            PropValue::Float(val) => Value::Float(*val as f64),
+            PropValue::Double(val) => Value::Float(*val),
+            PropValue::Bool(val) => Value::Bool(*val),
+            PropValue::String(val) => Value::String(val.clone()),
+            other => Value::Prop(other.clone()),
+        };
+    }
+
+    pub fn int_from_literal(literal: &str) -> Result<Self, String> {
+        return match literal.parse::<i128>() {
+            Ok(val) => Ok(Value::Int(val)),
+            Err(_) => Err("Integer literal `".to_owned() + literal + "` is out of range"),
+        };
+    }
+
+    pub fn float_from_literal(literal: &str) -> Result<Self, String> {
+        return match literal.parse::<f64>() {
+            Ok(val) if val.is_finite() => Ok(Value::Float(val)),
+            _ => Err("Float literal `".to_owned() + literal + "` is out of range"),
+        };
+    }
+
+    /// Attaches a unit suffix to a number literal.
+    pub fn with_unit(self, unit: &'static Unit) -> Self {
+        let Some(val) = self.as_float() else {
+            return self;
+        };
+        return Value::Quantity {
+            value: unit.to_canonical(val),
+            unit: unit,
+        };
+    }
+
+    /// Unit the value was written in, `None` for values without a unit.
+    pub fn unit(&self) -> Option<&'static Unit> {
+        return match self {
+            Value::Quantity { unit, .. } => Some(unit),
+            _ => None,
+        };
+    }
+
+    pub fn type_name(&self) -> String {
+        return match self {
+            Value::Int(_) => "int".to_string(),
+            Value::Float(_) => "float".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::String(_) => "string".to_string(),
+            Value::Quantity { unit, .. } => unit.dimension.name().to_string(),
+            Value::Prop(value) => match value.prop_type() {
+                Some(ty) => ty.to_string(),
+                None => "Err".to_string(),
+            },
+        };
+    }
+
+    fn not_whole(&self, ty: &PropType) -> String {
+        let Value::Quantity { value, unit } = self else {
+            return self.out_of_range(ty);
+        };
+        let canonical = unit.dimension.canonical();
+        return "Value `".to_owned()
+            + &unit.from_canonical(*value).to_string()
+            + unit.name
+            + "` is `"
+            + &value.to_string()
+            + canonical.name
+            + "`, which is not a whole number as prop type `"
+            + &ty.to_string()
+            + "` requires";
+    }
+
+    fn out_of_range(&self, ty: &PropType) -> String {
+        let literal = match self {
+            Value::Int(val) => val.to_string(),
+            Value::Float(val) => val.to_string(),
+            Value::Quantity { value, unit } => unit.from_canonical(*value).to_string() + unit.name,
+            _ => self.type_name(),
+        };
+        return "Value `".to_owned()
+            + &literal
+            + "` is out of range for prop type `"
+            + &ty.to_string()
+            + "`";
+    }
+
+    /// Narrows the value to the declared prop type, checking integer ranges. Quantities are
+    /// stored in their canonical unit and only fit integer types when that is a whole number.
+    /// Durations, dates and datetimes are read from strings, durations also from times.
+    pub fn to_prop_value(self, ty: &PropType) -> Result<PropValue, String> {
+        if let Some(value) = self.to_time_value(ty) {
+            return value;
+        }
+
+        if let Value::Quantity { value, .. } = self {
+            return match ty {
+                PropType::Float | PropType::Double => Value::Float(value).to_prop_value(ty),
+                PropType::Int | PropType::Long | PropType::UInt if value.fract() == 0.0 => {
+                    Value::Int(value as i128)
+                        .to_prop_value(ty)
+                        .map_err(|_| self.out_of_range(ty))
+                }
+                PropType::Int | PropType::Long | PropType::UInt => Err(self.not_whole(ty)),
+                _ => Err(TYPE_MISMATCH.to_string()),
+            };
+        }
+
+        let converted = match (&self, ty) {
+            (Value::Int(val), PropType::Int) => i32::try_from(*val).ok().map(PropValue::Int),
+            (Value::Int(val), PropType::Long) => i64::try_from(*val).ok().map(PropValue::Long),
+            (Value::Int(val), PropType::UInt) => u64::try_from(*val).ok().map(PropValue::UInt),
+            (Value::Int(val), PropType::Float) => Some(PropValue::Float(*val as f32)),
+            (Value::Int(val), PropType::Double) => Some(PropValue::Double(*val as f64)),
+            (Value::Float(val), PropType::Float) => {
+                Some(PropValue::Float(*val as f32)).filter(|_| (*val as f32).is_finite())
+            }
+            (Value::Float(val), PropType::Double) => Some(PropValue::Double(*val)),
+            (Value::Bool(val), PropType::Bool) => Some(PropValue::Bool(*val)),
+            (Value::String(val), PropType::String) => Some(PropValue::String(val.clone())),
+            (Value::Prop(value), ty) => match value.convert(ty) {
+                Some(value) => Some(value),
+                None => return Err(TYPE_MISMATCH.to_string()),
+            },
+            _ => return Err(TYPE_MISMATCH.to_string()),
+        };
+
+        return match converted {
+            Some(value) => Ok(value),
+            None => Err(self.out_of_range(ty)),
+        };
+    }
+
+    fn to_time_value(&self, ty: &PropType) -> Option<Result<PropValue, String>> {
+        let invalid = |literal: &str| {
+            return Err("Invalid `".to_owned() + &ty.to_string() + "` value `" + literal + "`");
+        };
+
+        let value = match (self, ty) {
+            (Value::String(literal), PropType::Duration) => match parse_duration(literal) {
+                Some(val) => Ok(PropValue::Duration(val)),
+                None => invalid(literal),
+            },
+            (Value::String(literal), PropType::Date) => match Date::from_literal(literal) {
+                Some(val) => Ok(PropValue::Date(val)),
+                None => invalid(literal),
+            },
+            (Value::String(literal), PropType::DateTime) => match DateTime::from_literal(literal) {
+                Some(val) => Ok(PropValue::DateTime(val)),
+                None => invalid(literal),
+            },
+            (Value::Quantity { value, unit }, PropType::Duration)
+                if unit.dimension == Dimension::Time =>
+            {
+                match Duration::try_from_secs_f64(*value) {
+                    Ok(val) => Ok(PropValue::Duration(val)),
+                    Err(_) => Err(self.out_of_range(ty)),
+                }
+            }
+            _ => return None,
+        };
+        return Some(value);
+    }
+
+    /// Picks a prop type for the value of an untyped prop: the narrowest of `int`, `long` and
+    /// `uint` for integers and `float` for decimals. Quantities are always `double`, so that they
+    /// convert back to the unit they were written in without loss.
+    pub fn infer_prop_value(self) -> Result<PropValue, String> {
+        return match self {
+            Value::Int(val) => {
+                for ty in [PropType::Int, PropType::Long, PropType::UInt] {
+                    if let Ok(value) = Value::Int(val).to_prop_value(&ty) {
+                        return Ok(value);
+                    }
+                }
+                Err(Value::Int(val).out_of_range(&PropType::UInt))
+            }
+            Value::Float(_) => self.to_prop_value(&PropType::Float),
+            Value::Quantity { .. } => self.to_prop_value(&PropType::Double),
+            Value::Bool(val) => Ok(PropValue::Bool(val)),
+            Value::String(val) => Ok(PropValue::String(val)),
+            Value::Prop(value) => Ok(value),
+        };
+    }
+
+    pub fn unary(op: &str, operand: Value) -> Result<Value, String> {
+        return match (op, &operand) {
+            ("-", Value::Int(val)) => match val.checked_neg() {
+                Some(val) => Ok(Value::Int(val)),
+                None => Err("Integer overflow".to_string()),
+            },
+            ("-", Value::Float(val)) => Ok(Value::Float(-val)),
+            ("-", Value::Quantity { value, unit }) => Ok(Value::Quantity {
+                value: -value,
+                unit: unit,
+            }),
+            ("!", Value::Bool(val)) => Ok(Value::Bool(!val)),
+            _ => Err("Operator `".to_owned()
+                + op
+                + "` cannot be applied to `"
+                + &operand.type_name()
+                + "`"),
+        };
+    }
+
+    pub fn binary(op: &str, lhs: Value, rhs: Value) -> Result<Value, String> {
+        let mismatch = || {
+            return "Operator `".to_owned()
+                + op
+                + "` cannot be applied to `"
+                + &lhs.type_name()
+                + "` and `"
+                + &rhs.type_name()
+                + "`";
+        };
+
+        match op {
+            "&&" | "||" => {
+                let (Value::Bool(l), Value::Bool(r)) = (&lhs, &rhs) else {
+                    return Err(mismatch());
+                };
+                return Ok(Value::Bool(if op == "&&" { *l && *r } else { *l || *r }));
+            }
+            "==" | "!=" => {
+                let Some(ordering) = Self::compare(&lhs, &rhs, true) else {
+                    return Err(mismatch());
+                };
+                return Ok(Value::Bool((ordering == Ordering::Equal) == (op == "==")));
+            }
+            "<" | "<=" | ">" | ">=" => {
+                let Some(ordering) = Self::compare(&lhs, &rhs, false) else {
+                    return Err(mismatch());
+                };
+                return Ok(Value::Bool(match op {
+                    "<" => ordering == Ordering::Less,
+                    "<=" => ordering != Ordering::Greater,
+                    ">" => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }));
+            }
+            _ => {}
+        }
+
+        if let (Value::String(l), Value::String(r), "+") = (&lhs, &rhs, op) {
+            return Ok(Value::String(l.clone() + r));
+        }
+
+        if lhs.unit().is_some() || rhs.unit().is_some() {
+            let rhs_zero = match &rhs {
+                Value::Quantity { value, .. } => *value == 0.0,
+                rhs => rhs.as_float() == Some(0.0),
+            };
+            if op == "/" && rhs_zero {
+                return Err("Division by zero".to_string());
+            }
+            return Self::binary_quantity(op, &lhs, &rhs).ok_or_else(mismatch);
+        }
+
+        if let (Value::Int(l), Value::Int(r)) = (&lhs, &rhs) {
+            if (op == "/" || op == "%") && *r == 0 {
+                return Err("Division by zero".to_string());
+            }
+
+            let result = match op {
+                "+" => l.checked_add(*r),
+                "-" => l.checked_sub(*r),
+                "*" => l.checked_mul(*r),
+                "/" => l.checked_div(*r),
+                "%" => l.checked_rem(*r),
+                _ => return Err(mismatch()),
+            };
+            return match result {
+                Some(val) => Ok(Value::Int(val)),
+                None => Err("Integer overflow".to_string()),
+            };
+        }
+
+        let (Some(l), Some(r)) = (lhs.as_float(), rhs.as_float()) else {
+            return Err(mismatch());
+        };
+
+        if (op == "/" || op == "%") && r == 0.0 {
+            return Err("Division by zero".to_string());
+        }
+
+        return match op {
+            "+" => Ok(Value::Float(l + r)),
+            "-" => Ok(Value::Float(l - r)),
+            "*" => Ok(Value::Float(l * r)),
+            "/" => Ok(Value::Float(l / r)),
+            "%" => Ok(Value::Float(l % r)),
+            _ => Err(mismatch()),
+        };
+    }
+
+    /// Arithmetic with at least one quantity. Quantities of one dimension add up, and scale by
+    /// plain numbers. `None` when the operands don't fit together.
+    fn binary_quantity(op: &str, lhs: &Value, rhs: &Value) -> Option<Value> {
+        let quantity = |value: f64, unit: &'static Unit| Value::Quantity {
+            value: value,
+            unit: unit,
+        };
+
+        return match (lhs, rhs, op) {
+            (
+                Value::Quantity { value: l, unit: lu },
+                Value::Quantity { value: r, unit: ru },
+                "+" | "-" | "/",
+            ) if lu.dimension == ru.dimension => {
+                // mixed units fall back to the canonical one
+                let unit = if lu == ru {
+                    *lu
+                } else {
+                    lu.dimension.canonical()
+                };
+                match op {
+                    "+" => Some(quantity(l + r, unit)),
+                    "-" => Some(quantity(l - r, unit)),
+                    _ => Some(Value::Float(l / r)),
+                }
+            }
+            (Value::Quantity { value: l, unit }, rhs, "*" | "/") => {
+                let r = rhs.as_float()?;
+                match op {
+                    "*" => Some(quantity(l * r, unit)),
+                    _ => Some(quantity(l / r, unit)),
+                }
+            }
+            (lhs, Value::Quantity { value: r, unit }, "*") => {
+                Some(quantity(lhs.as_float()? * r, unit))
+            }
+            _ => None,
+        };
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        return match self {
+            Value::Int(val) => Some(*val as f64),
+            Value::Float(val) => Some(*val),
+            _ => None,
+        };
+    }
+
+    fn compare(lhs: &Value, rhs: &Value, equality: bool) -> Option<Ordering> {
+        return match (lhs, rhs) {
+            (Value::Int(l), Value::Int(r)) => Some(l.cmp(r)),
+            (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+            (Value::Quantity { value: l, unit: lu }, Value::Quantity { value: r, unit: ru })
+                if lu.dimension == ru.dimension =>
+            {
+                l.partial_cmp(r)
+            }
+            (Value::Quantity { .. }, _) | (_, Value::Quantity { .. }) => None,
+            (Value::Bool(l), Value::Bool(r)) if equality => Some(l.cmp(r)),
+            (Value::Prop(l), Value::Prop(r)) if equality && l.prop_type() == r.prop_type() => {
+                Some(if l == r {
+                    Ordering::Equal
+                } else {
+                    Ordering::Less
+                })
+            }
+            _ => lhs.as_float()?.partial_cmp(&rhs.as_float()?),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_arithmetic_stays_exact() {
+        let val = Value::binary("*", Value::Int(i64::MAX as i128), Value::Int(2)).unwrap();
+        assert_eq!(val, Value::Int(i64::MAX as i128 * 2));
+        assert_eq!(
+            Value::binary("/", Value::Int(7), Value::Int(2)).unwrap(),
+            Value::Int(3)
+        );
+    }
+
+    #[test]
+    fn mixed_arithmetic_promotes_to_float() {
+        assert_eq!(
+            Value::binary("/", Value::Float(1.0), Value::Int(4)).unwrap(),
+            Value::Float(0.25)
+        );
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(
+            Value::binary("/", Value::Int(1), Value::Int(0)).unwrap_err(),
+            "Division by zero"
+        );
+        assert_eq!(
+            Value::binary("%", Value::Float(1.0), Value::Int(0)).unwrap_err(),
+            "Division by zero"
+        );
+    }
+
+    #[test]
+    fn operators_reject_mismatched_types() {
+        assert_eq!(
+            Value::binary("+", Value::Int(1), Value::String("a".to_string())).unwrap_err(),
+            "Operator `+` cannot be applied to `int` and `string`"
+        );
+        assert_eq!(
+            Value::unary("!", Value::Int(1)).unwrap_err(),
+            "Operator `!` cannot be applied to `int`"
+        );
+    }
+
+    #[test]
+    fn values_narrow_to_declared_types_with_range_checks() {
+        assert_eq!(
+            Value::Int(-1).to_prop_value(&PropType::Long),
+            Ok(PropValue::Long(-1))
+        );
+        assert_eq!(
+            Value::Int(-1).to_prop_value(&PropType::UInt).unwrap_err(),
+            "Value `-1` is out of range for prop type `uint`"
+        );
+        assert_eq!(
+            Value::Float(0.5).to_prop_value(&PropType::Int).unwrap_err(),
+            TYPE_MISMATCH
+        );
+        assert_eq!(
+            Value::Int(3_000_000_000).infer_prop_value(),
+            Ok(PropValue::Long(3_000_000_000))
+        );
+    }
+
+    #[test]
+    fn quantities_scale_and_compare_within_a_dimension() {
+        let m = Unit::lookup("m").unwrap();
+        let cm = Unit::lookup("cm").unwrap();
+        let two_m = Value::Int(2).with_unit(m);
+
+        assert_eq!(
+            Value::binary("*", two_m.clone(), Value::Int(3)).unwrap(),
+            Value::Float(6.0).with_unit(m)
+        );
+        assert_eq!(
+            Value::binary("/", two_m.clone(), Value::Int(50).with_unit(cm)).unwrap(),
+            Value::Float(4.0)
+        );
+        assert_eq!(
+            Value::binary(">", two_m.clone(), Value::Int(150).with_unit(cm)).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            Value::binary("/", two_m, Value::Int(0)).unwrap_err(),
+            "Division by zero"
+        );
+    }
+}
@@ -1,11 +1,28 @@
 use std::char;
 
+use crate::limits::{Limit, ParseLimits};
+
+/// Symbols made of two characters, every other symbol is a single character.
+const OPERATORS: [&str; 6] = ["==", "!=", "<=", ">=", "&&", "||"];
+
 #[derive(PartialEq, Debug)]
 pub enum TokenKind {
     String,
     Number,
     Word,
     Symbol,
+    /// A `///` comment, documenting the declaration below it.
+    DocComment,
+    /// `#` followed by letters and digits, like `#ff8800`.
+    Color,
+    /// Input the lexer refused, the literal holds the error message. Always the last token.
+    Error(LexError),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LexError {
+    UnterminatedString,
+    Limit(Limit),
 }
 
 #[derive(PartialEq, Debug)]
@@ -14,17 +31,26 @@ pub struct Token {
     pub literal: String,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub struct TokenInfo {
     pub line: usize,
     pub col: usize,
 }
 
+/// Source range of a declaration, from its first token to the token that starts its body or value.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Span {
+    pub start: TokenInfo,
+    pub end: TokenInfo,
+}
+
 pub struct Lexer {
     source: Vec<char>,
     index: usize,
     line: usize,
     last_line_index: usize,
+    max_string_bytes: usize,
+    failed: bool,
 }
 
 impl Token {
@@ -55,11 +81,17 @@ impl Iterator for Lexer {
 
 impl Lexer {
     pub fn new(source: &str) -> Self {
+        return Self::with_limits(source, &ParseLimits::default());
+    }
+
+    pub fn with_limits(source: &str, limits: &ParseLimits) -> Self {
         return Self {
             source: source.chars().collect(),
             index: 0,
             line: 0,
             last_line_index: 0,
+            max_string_bytes: limits.max_string_bytes,
+            failed: false,
         };
     }
 
@@ -75,7 +107,7 @@ impl Lexer {
 
         let item = self.source.get(peek_index as usize);
         match item {
-            Some(c) => return c.clone(),
+            Some(c) => return *c,
             None => return '\0',
         }
     }
@@ -91,8 +123,20 @@ impl Lexer {
     }
 
     fn consume(&mut self) -> Option<(Token, TokenInfo)> {
+        if self.failed {
+            return None;
+        }
         self.skip_whitespace();
 
+        // `//` comments are skipped, `///` (but not `////`) are kept as doc comments
+        while self.peek() == '/' && self.peek_offset(1) == '/' {
+            if self.peek_offset(2) == '/' && self.peek_offset(3) != '/' {
+                break;
+            }
+            self.consume_line();
+            self.skip_whitespace();
+        }
+
         let token_info = TokenInfo {
             line: self.line,
             col: (self.index - self.last_line_index),
@@ -100,23 +144,54 @@ impl Lexer {
 
         let c = self.peek();
         match c {
+            '/' if self.peek_offset(1) == '/' => {
+                let literal = self.consume_line();
+                return Some((Token::new(TokenKind::DocComment, &literal), token_info));
+            }
             '0'..='9' => return Some((self.consume_number(), token_info)),
             'A'..='Z' | 'a'..='z' => return Some((self.consume_word(), token_info)),
-            '"' => return Some((self.consume_string(), token_info)),
+            '#' if self.peek_offset(1).is_ascii_alphanumeric() => {
+                self.index += 1;
+                let word = self.consume_word();
+                let token = Token::new(TokenKind::Color, &("#".to_owned() + &word.literal));
+                return Some((token, token_info));
+            }
+            '"' => {
+                let token = self.consume_string();
+                self.failed = matches!(token.kind, TokenKind::Error(_));
+                return Some((token, token_info));
+            }
             '\0' => return None,
             _ => return Some((self.consume_char(), token_info)),
         }
     }
 
+    fn consume_line(&mut self) -> String {
+        let mut literal = String::new();
+        while self.peek() != '\n' && self.peek() != '\0' {
+            literal.push(self.peek());
+            self.index += 1;
+        }
+        return literal.trim_end().to_string();
+    }
+
     fn consume_char(&mut self) -> Token {
         let c = self.peek();
         if c != '\0' {
             self.index += 1;
         }
 
+        let mut literal = c.to_string();
+        literal.push(self.peek());
+        if OPERATORS.contains(&literal.as_str()) {
+            self.index += 1;
+        } else {
+            literal.pop();
+        }
+
         return Token {
             kind: TokenKind::Symbol,
-            literal: c.to_string(),
+            literal: literal,
         };
     }
 
@@ -124,7 +199,7 @@ impl Lexer {
         let mut literal = String::new();
 
         while self.peek().is_alphanumeric() || self.peek() == '_' {
-            literal.push(self.peek().clone());
+            literal.push(self.peek());
             self.index += 1;
         }
 
@@ -138,7 +213,7 @@ impl Lexer {
         let mut literal = String::new();
 
         while self.peek().is_numeric() || (self.peek() == '.' && self.peek_offset(1).is_numeric()) {
-            literal.push(self.peek().clone());
+            literal.push(self.peek());
             self.index += 1;
         }
 
@@ -154,12 +229,31 @@ impl Lexer {
         self.index += 1;
 
         while self.peek() != '"' || self.peek_offset(-1) == '\\' {
-            literal.push(self.peek().clone());
+            if self.peek() == '\0' && self.index >= self.source.len() {
+                return Token {
+                    kind: TokenKind::Error(LexError::UnterminatedString),
+                    literal: "Unterminated string literal".to_string(),
+                };
+            }
+
+            // the opening quote is part of the literal
+            if literal.len() - 1 + self.peek().len_utf8() > self.max_string_bytes {
+                let limits = ParseLimits {
+                    max_string_bytes: self.max_string_bytes,
+                    ..ParseLimits::default()
+                };
+                return Token {
+                    kind: TokenKind::Error(LexError::Limit(Limit::StringBytes)),
+                    literal: limits.message(Limit::StringBytes),
+                };
+            }
+
+            literal.push(self.peek());
             self.index += 1;
         }
 
         // add closing quote
-        literal.push(self.peek().clone());
+        literal.push(self.peek());
         self.index += 1;
 
         return Token {
@@ -239,6 +333,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_operation)]
     fn alphabetics_after_numerics_are_separate_word_tokens() {
         let mut lexer = Lexer::new("123world");
         lexer.next().unwrap().0;
@@ -334,4 +429,88 @@ mod tests {
             (Token::new(TokenKind::Number, "3"), TokenInfo::new(2, 5))
         );
     }
+
+    #[test]
+    fn two_character_operators_are_single_symbols() {
+        let mut lexer = Lexer::new("a == b != c <= d >= e && f || g = h < i");
+        let symbols: Vec<String> = lexer
+            .by_ref()
+            .filter(|(token, _)| token.kind == TokenKind::Symbol)
+            .map(|(token, _)| token.literal)
+            .collect();
+        assert_eq!(symbols, vec!["==", "!=", "<=", ">=", "&&", "||", "=", "<"]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let mut lexer = Lexer::new("// comment\nhello // trailing\n//// banner\nworld");
+        assert_eq!(
+            lexer.next().unwrap(),
+            (Token::new(TokenKind::Word, "hello"), TokenInfo::new(1, 0))
+        );
+        assert_eq!(
+            lexer.next().unwrap(),
+            (Token::new(TokenKind::Word, "world"), TokenInfo::new(3, 0))
+        );
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn doc_comments_are_tokens() {
+        let mut lexer = Lexer::new("  /// Docs for x\r\nx");
+        assert_eq!(
+            lexer.next().unwrap(),
+            (
+                Token::new(TokenKind::DocComment, "/// Docs for x"),
+                TokenInfo::new(0, 2)
+            )
+        );
+        assert_eq!(lexer.next().unwrap().0, Token::new(TokenKind::Word, "x"));
+    }
+
+    #[test]
+    fn unterminated_strings_end_with_an_error() {
+        let mut lexer = Lexer::new("x = \"never closed");
+        lexer.next();
+        lexer.next();
+        assert_eq!(
+            lexer.next().unwrap(),
+            (
+                Token::new(
+                    TokenKind::Error(LexError::UnterminatedString),
+                    "Unterminated string literal"
+                ),
+                TokenInfo::new(0, 4)
+            )
+        );
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn strings_longer_than_the_limit_are_errors() {
+        let limits = ParseLimits {
+            max_string_bytes: 5,
+            ..ParseLimits::default()
+        };
+        let mut lexer = Lexer::with_limits("\"12345\" \"123456\" after", &limits);
+        assert_eq!(lexer.next().unwrap().0.kind, TokenKind::String);
+        assert_eq!(
+            lexer.next().unwrap().0,
+            Token::new(
+                TokenKind::Error(LexError::Limit(Limit::StringBytes)),
+                "String length in bytes exceeds the limit of 5"
+            )
+        );
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn hash_starts_a_color_token() {
+        let mut lexer = Lexer::new("#ff8800 # x");
+        assert_eq!(
+            lexer.next().unwrap().0,
+            Token::new(TokenKind::Color, "#ff8800")
+        );
+        assert_eq!(lexer.next().unwrap().0, Token::new(TokenKind::Symbol, "#"));
+    }
 }
@@ -1,4 +1,17 @@
+pub mod color;
 pub mod core;
+pub mod document;
+pub mod env;
+pub mod expr;
 pub mod lexer;
+pub mod limits;
+pub mod loader;
 pub mod parser;
 pub mod string_utils;
+pub mod time;
+pub mod types;
+pub mod units;
+pub mod vector;
+pub mod version;
+
+pub use document::{parse_file, parse_reader, parse_str, Document, Error};
@@ -0,0 +1,107 @@
+use std::fmt;
+
+/// Nesting of parentheses and unary operators within a prop value that is never exceeded, not
+/// even with [`ParseLimits::unlimited`], since every level takes up stack while it is parsed.
+pub const MAX_VALUE_DEPTH: usize = 128;
+
+/// Bounds on the size of parsed input, protecting against hostile or runaway files.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ParseLimits {
+    /// Nesting of things, and of parentheses and unary operators within prop values. The latter
+    /// is capped at [`MAX_VALUE_DEPTH`] regardless.
+    pub max_depth: usize,
+    pub max_tokens: usize,
+    /// Length of a single string, checked for literals and for strings built by expressions.
+    pub max_string_bytes: usize,
+    /// Things in the whole file, counting copies made by `extends`.
+    pub max_things: usize,
+    /// Props in the whole file, counting copies made by `extends`.
+    pub max_props: usize,
+    /// Nesting of files pulled in by other files. Reserved until FDL has an include directive,
+    /// nothing checks it yet.
+    pub max_include_depth: usize,
+}
+
+/// The limit a [`ParseLimits`] check failed on.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Limit {
+    Depth,
+    Tokens,
+    StringBytes,
+    Things,
+    Props,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        return Self {
+            max_depth: 64,
+            max_tokens: 1_000_000,
+            max_string_bytes: 64 * 1024,
+            max_things: 10_000,
+            max_props: 100_000,
+            max_include_depth: 8,
+        };
+    }
+}
+
+impl ParseLimits {
+    pub fn unlimited() -> Self {
+        return Self {
+            max_depth: usize::MAX,
+            max_tokens: usize::MAX,
+            max_string_bytes: usize::MAX,
+            max_things: usize::MAX,
+            max_props: usize::MAX,
+            max_include_depth: usize::MAX,
+        };
+    }
+
+    pub fn max(&self, limit: Limit) -> usize {
+        return match limit {
+            Limit::Depth => self.max_depth,
+            Limit::Tokens => self.max_tokens,
+            Limit::StringBytes => self.max_string_bytes,
+            Limit::Things => self.max_things,
+            Limit::Props => self.max_props,
+        };
+    }
+
+    /// Message for exceeding `limit`, e.g. "Nesting depth exceeds the limit of 64".
+    pub fn message(&self, limit: Limit) -> String {
+        return limit.to_string() + " exceeds the limit of " + &self.max(limit).to_string();
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(match self {
+            Limit::Depth => "Nesting depth",
+            Limit::Tokens => "Token count",
+            Limit::StringBytes => "String length in bytes",
+            Limit::Things => "Thing count",
+            Limit::Props => "Prop count",
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_messages_name_the_limit_and_its_maximum() {
+        let limits = ParseLimits {
+            max_depth: 3,
+            ..ParseLimits::default()
+        };
+        assert_eq!(
+            limits.message(Limit::Depth),
+            "Nesting depth exceeds the limit of 3"
+        );
+        assert_eq!(
+            limits.message(Limit::StringBytes),
+            "String length in bytes exceeds the limit of 65536"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{
+    core::{Thing, Things},
+    document::{Document, Error},
+    parser::{Diagnostic, Parser, ParserBuilder},
+};
+
+/// Parses several files in order and merges them into one tree, so a base config can be
+/// refined by environment or user level overrides. Each layer is parsed on top of the merged
+/// layers before it: its `patch` blocks change their things, its refs and `extends` can point
+/// into them, and their enums, constants and `abstract` templates are in scope.
+///
+/// Things are matched by name: the n-th thing named `A` in a layer merges into the n-th thing
+/// named `A` of the layers before it. Props of later layers replace earlier ones, and children
+/// that don't match anything are appended, as are anonymous things. `${...}` placeholders are
+/// substituted and refs checked in the merged things after every layer, so placeholders see the
+/// values of later layers and refs can't be left dangling by them.
+#[derive(Debug, Default)]
+pub struct Loader {
+    layers: Vec<Layer>,
+    parser: ParserBuilder,
+}
+
+#[derive(Debug)]
+enum Layer {
+    File { path: PathBuf, optional: bool },
+    Source { name: String, source: String },
+}
+
+/// The result of a [`Loader`].
+#[derive(Debug)]
+pub struct Merged {
+    pub things: Things,
+    /// The parsed layers in load order, skipped optional files aren't included.
+    pub layers: Vec<LoadedLayer>,
+}
+
+/// A file or source merged by a [`Loader`], its things are part of [`Merged::things`].
+#[derive(Debug)]
+pub struct LoadedLayer {
+    /// The file path, or the name given to [`Loader::source`].
+    pub name: String,
+    pub source: String,
+    /// Problems that didn't stop parsing.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Loader {
+        self.layers.push(Layer::File {
+            path: path.into(),
+            optional: false,
+        });
+        return self;
+    }
+
+    /// Adds a file that is skipped when it doesn't exist, e.g. a user's local overrides.
+    pub fn optional_file(mut self, path: impl Into<PathBuf>) -> Loader {
+        self.layers.push(Layer::File {
+            path: path.into(),
+            optional: true,
+        });
+        return self;
+    }
+
+    /// Parses every layer with a copy of `builder`, e.g. for flags, block keywords, prop types,
+    /// the environment or limits. Things set with [`ParserBuilder::things`] are replaced by the
+    /// layers merged so far.
+    pub fn parser(mut self, builder: ParserBuilder) -> Loader {
+        self.parser = builder;
+        return self;
+    }
+
+    /// Adds a layer parsed from a string, `name` takes the place of the file name.
+    pub fn source(mut self, name: impl Into<String>, source: impl Into<String>) -> Loader {
+        self.layers.push(Layer::Source {
+            name: name.into(),
+            source: source.into(),
+        });
+        return self;
+    }
+
+    pub fn load(self) -> Result<Merged, Error> {
+        let mut earlier = Parser::new();
+        let mut layers = Vec::new();
+
+        for layer in self.layers {
+            let (name, source) = match layer {
+                Layer::File { path, optional } => {
+                    let name = path.display().to_string();
+                    match fs::read_to_string(&path) {
+                        Ok(source) => (name, source),
+                        Err(err) if optional && err.kind() == io::ErrorKind::NotFound => continue,
+                        Err(err) => return Err(Error::Layer(name, Box::new(err.into()))),
+                    }
+                }
+                Layer::Source { name, source } => (name, source),
+            };
+
+            let layer_error = |err: Error| Error::Layer(name.clone(), Box::new(err));
+            let builder = self.parser.clone().defer_interpolation();
+            let (document, mut parser) =
+                Document::parse_over(earlier, builder, source, Some(name.clone()))
+                    .map_err(layer_error)?;
+
+            merge_things(&mut parser.things, document.things);
+            parser
+                .interpolate_strings()
+                .map_err(|err| layer_error(err.into()))?;
+            for thing in parser.things.iter_mut() {
+                set_origin(thing, &name);
+            }
+            // reported in the layer the ref was declared in
+            parser.check_all_refs().map_err(|(err, origin)| {
+                Error::Layer(origin.unwrap_or(name.clone()), Box::new(err.into()))
+            })?;
+
+            layers.push(LoadedLayer {
+                name: name,
+                source: document.source,
+                diagnostics: document.diagnostics,
+            });
+            earlier = parser;
+        }
+
+        let merged = Merged {
+            things: earlier.things,
+            layers: layers,
+        };
+        return Ok(merged);
+    }
+}
+
+/// Sets the origin of the props that don't have one yet, i.e. those of the layer just merged.
+fn set_origin(thing: &mut Thing, origin: &str) {
+    for prop in thing
+        .props
+        .values_mut()
+        .filter(|prop| prop.origin.is_none())
+    {
+        prop.origin = Some(origin.to_string());
+    }
+    for child in thing.things.iter_mut() {
+        set_origin(child, origin);
+    }
+}
+
+fn merge_things(target: &mut Things, layer: Things) {
+    let mut seen: Vec<String> = Vec::new();
+    for thing in layer {
+        if thing.name.is_empty() {
+            target.push(thing);
+            continue;
+        }
+
+        let nth = seen.iter().filter(|name| **name == thing.name).count();
+        seen.push(thing.name.clone());
+        let existing = target.iter_mut().filter(|t| t.name == thing.name).nth(nth);
+        match existing {
+            Some(existing) => merge_thing(existing, thing),
+            None => target.push(thing),
+        }
+    }
+}
+
+fn merge_thing(target: &mut Thing, layer: Thing) {
+    if layer.kind.is_some() {
+        target.kind = layer.kind;
+    }
+    if layer.doc.is_some() {
+        target.doc = layer.doc;
+    }
+    for annotation in layer.annotations {
+        target.annotations.retain(|a| a.name != annotation.name);
+        target.annotations.push(annotation);
+    }
+    for (_, prop) in layer.props {
+        target.add_prop(prop);
+    }
+    merge_things(&mut target.things, layer.things);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::PropValue;
+
+    const BASE: &str = r#"
+        thing "Server" {
+            port = 80
+            host = "localhost"
+            thing "Tls" { enabled = false }
+        }
+        thing "Client" { retries = 3 }
+    "#;
+
+    #[test]
+    fn later_layers_override_props() {
+        let merged = Loader::new()
+            .source("base.fdl", BASE)
+            .source(
+                "prod.fdl",
+                r#"thing "Server" { port = 443 thing "Tls" { enabled = true } }"#,
+            )
+            .load()
+            .unwrap();
+
+        let server = merged.things.get("Server").unwrap();
+        assert_eq!(server.props["port"].value, PropValue::Int(443));
+        assert_eq!(server.props["port"].origin.as_deref(), Some("prod.fdl"));
+        assert_eq!(server.props["host"].origin.as_deref(), Some("base.fdl"));
+        assert_eq!(
+            server.get_thing("Tls").unwrap().props["enabled"].value,
+            PropValue::Bool(true)
+        );
+        assert_eq!(merged.things.len(), 2);
+        assert_eq!(merged.layers.len(), 2);
+    }
+
+    #[test]
+    fn unmatched_things_are_appended() {
+        let merged = Loader::new()
+            .source("base.fdl", BASE)
+            .source(
+                "user.fdl",
+                r#"thing "Server" { thing "Cache" { } } thing "Logs" { }"#,
+            )
+            .load()
+            .unwrap();
+
+        let server = merged.things.get("Server").unwrap();
+        assert_eq!(server.things.len(), 2);
+        assert_eq!(server.things.at(1).unwrap().name, "Cache");
+        assert_eq!(merged.things.at(2).unwrap().name, "Logs");
+    }
+
+    #[test]
+    fn patches_change_earlier_layers() {
+        let merged = Loader::new()
+            .source("base.fdl", BASE)
+            .source(
+                "user.fdl",
+                r#"patch "Server/Tls" { enabled = true } patch "Server" { unset host }"#,
+            )
+            .load()
+            .unwrap();
+
+        let server = merged.things.get("Server").unwrap();
+        assert!(!server.props.contains_key("host"));
+        assert_eq!(server.props["port"].origin.as_deref(), Some("base.fdl"));
+        let tls = server.get_thing("Tls").unwrap();
+        assert_eq!(tls.props["enabled"].value, PropValue::Bool(true));
+        assert_eq!(tls.props["enabled"].origin.as_deref(), Some("user.fdl"));
+        assert_eq!(merged.layers[1].name, "user.fdl");
+    }
+
+    #[test]
+    fn enums_constants_and_templates_carry_over() {
+        let merged = Loader::new()
+            .source(
+                "base.fdl",
+                r#"
+                enum Level { A, B }
+                const LIMIT = 10
+                abstract thing "Enemy" { hp = 5 }
+                thing "S" { Level level = A }
+            "#,
+            )
+            .source(
+                "user.fdl",
+                r#"
+                thing "S" { Level level = B  limit = LIMIT }
+                thing "Orc" extends "Enemy" { }
+            "#,
+            )
+            .load()
+            .unwrap();
+
+        let s = merged.things.get("S").unwrap();
+        assert_eq!(
+            s.props["level"].value,
+            PropValue::Enum {
+                ty: "Level".to_string(),
+                variant: "B".to_string()
+            }
+        );
+        assert_eq!(s.props["limit"].value, PropValue::Int(10));
+        let orc = merged.things.get("Orc").unwrap();
+        assert_eq!(orc.props["hp"].value, PropValue::Int(5));
+    }
+
+    #[test]
+    fn interpolation_sees_later_layers() {
+        let merged = Loader::new()
+            .source(
+                "base.fdl",
+                r#"thing "Server" { port = 80  host = "h"  url = "${host}:${port}" }"#,
+            )
+            .source(
+                "prod.fdl",
+                r#"thing "Server" { port = 443  label = "${host} (prod)" }"#,
+            )
+            .load()
+            .unwrap();
+
+        let server = merged.things.get("Server").unwrap();
+        assert_eq!(
+            server.props["url"].value,
+            PropValue::String("h:443".to_string())
+        );
+        assert_eq!(
+            server.props["label"].value,
+            PropValue::String("h (prod)".to_string())
+        );
+        assert_eq!(server.props["url"].origin.as_deref(), Some("base.fdl"));
+    }
+
+    #[test]
+    fn layers_are_parsed_with_the_given_builder() {
+        let merged = Loader::new()
+            .parser(Parser::build().flag("prod").block_keyword("server"))
+            .source("base.fdl", r#"server "A" { port = 80 }"#)
+            .source(
+                "prod.fdl",
+                r#"if flag("prod") { server "A" { port = 443 } }"#,
+            )
+            .load()
+            .unwrap();
+
+        let server = merged.things.get("A").unwrap();
+        assert!(server.is_kind("server"));
+        assert_eq!(server.props["port"].value, PropValue::Int(443));
+    }
+
+    #[test]
+    fn refs_are_checked_after_later_layers() {
+        let base = r#"
+            thing "World" { thing "Loot" { } }
+            thing "Chest" { ref contents = @"World/Loot" }
+        "#;
+        let err = Loader::new()
+            .source("base.fdl", base)
+            .source("user.fdl", r#"patch "World" { remove thing "Loot" }"#)
+            .load()
+            .unwrap_err();
+        let Error::Layer(name, inner) = &err else {
+            panic!("expected a layer error, got {:?}", err);
+        };
+        assert_eq!(name, "base.fdl");
+        let Error::Parse(parse_err) = inner.as_ref() else {
+            panic!("expected a parse error, got {:?}", inner);
+        };
+        assert_eq!(parse_err.message, "Unresolved reference `World/Loot`");
+        assert_eq!(parse_err.token_info.line, 2);
+
+        let merged = Loader::new()
+            .source("base.fdl", base)
+            .source(
+                "user.fdl",
+                r#"patch "World" { remove thing "Loot" } thing "World" { thing "Loot" { } }"#,
+            )
+            .load()
+            .unwrap();
+        assert_eq!(merged.things.get("World").unwrap().num_things(), 1);
+    }
+
+    #[test]
+    fn errors_name_their_layer() {
+        let err = Loader::new()
+            .source("base.fdl", BASE)
+            .source("user.fdl", r#"thing "Server" { port = }"#)
+            .load()
+            .unwrap_err();
+        assert!(matches!(&err, Error::Layer(name, _) if name == "user.fdl"));
+        assert!(err.to_string().starts_with("user.fdl: line 0:24"));
+    }
+
+    #[test]
+    fn missing_optional_files_are_skipped() {
+        let merged = Loader::new()
+            .source("base.fdl", BASE)
+            .optional_file("does/not/exist.fdl")
+            .load()
+            .unwrap();
+        assert_eq!(merged.layers.len(), 1);
+
+        let err = Loader::new().file("does/not/exist.fdl").load().unwrap_err();
+        assert!(matches!(err, Error::Layer(_, inner) if matches!(*inner, Error::Io(_))));
+    }
+}
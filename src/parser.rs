@@ -1,21 +1,206 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::iter::Peekable;
+use std::rc::Rc;
 
 use crate::{
-    core::{Prop, PropValue, Thing},
-    lexer::{Token, TokenInfo, TokenKind},
-    string_utils::strip_quotes,
+    color::Color,
+    core::{Annotation, EnumDef, Prop, PropType, PropValue, Template, Thing, Things},
+    env::{Environment, StdEnvironment},
+    expr::{Value, TYPE_MISMATCH},
+    lexer::{LexError, Span, Token, TokenInfo, TokenKind},
+    limits::{Limit, ParseLimits, MAX_VALUE_DEPTH},
+    string_utils::{
+        decode_base64, decode_hex, split_template, strip_doc_comment, strip_quotes, TemplatePart,
+    },
+    types::PropTypes,
+    units::Unit,
+    vector::{Vec2, Vec3, Vec4},
+    version::{Feature, Version, CURRENT},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseError {
     pub token_info: TokenInfo,
     pub message: String,
+    pub kind: ParseErrorKind,
+}
+
+/// A problem that doesn't stop parsing.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub token_info: TokenInfo,
+    pub message: String,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParseErrorKind {
+    Syntax,
+    /// The input exceeded one of the configured [`ParseLimits`].
+    LimitExceeded(Limit),
+}
+
+/// Pending entries locate their thing by child indices rather than names, since siblings may
+/// share a name or have none.
+#[derive(Debug, Clone)]
+struct PendingRef {
+    owner: Vec<usize>,
+    prop: String,
+    target: String,
+    token_info: TokenInfo,
+}
+
+/// A `thing ... extends "Base"` declaration, applied once the whole tree is parsed.
+#[derive(Debug)]
+struct PendingBase {
+    path: Vec<usize>,
+    base: String,
+    token_info: TokenInfo,
+}
+
+/// A string prop containing `${...}` placeholders, substituted once the whole tree is parsed.
+#[derive(Debug)]
+struct PendingInterpolation {
+    owner: Vec<usize>,
+    prop: String,
+    template: Rc<Template>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum VisitState {
+    Visiting,
+    Done,
+}
+
+/// A step of parsing, as produced by [`PullParser`].
+#[derive(Debug, Clone)]
+pub enum Event {
+    StartThing {
+        name: String,
+        kind: Option<String>,
+        /// Path of the `extends` base and where it was written.
+        base: Option<(String, TokenInfo)>,
+        is_abstract: bool,
+        annotations: Vec<Annotation>,
+        doc: Option<String>,
+        span: Span,
+    },
+    Prop {
+        name: String,
+        value: PropValue,
+        unit: Option<String>,
+        annotations: Vec<Annotation>,
+        doc: Option<String>,
+        span: Span,
+    },
+    EndThing,
+    /// Start of a `patch "Path" { ... }` block, whose statements change the thing at `path`.
+    StartPatch {
+        path: String,
+        span: Span,
+    },
+    /// `unset name` in a patch block.
+    Unset {
+        name: String,
+        span: Span,
+    },
+    /// `remove thing "Name"` in a patch block.
+    RemoveThing {
+        name: String,
+        span: Span,
+    },
+    EndPatch,
+    /// Always the last event.
+    Error(ParseError),
 }
 
 #[derive(Debug)]
 pub struct Parser {
-    pub things: HashMap<String, Thing>,
+    pub things: Things,
+    pub enums: HashMap<String, EnumDef>,
+    /// Constants declared at the top level.
+    pub constants: HashMap<String, PropValue>,
+    /// `abstract` things keyed by their path, only usable as bases of other things.
+    pub templates: HashMap<String, Thing>,
+    /// Version from the leading `fdl` directive, `None` when the file doesn't declare one.
+    pub version: Option<Version>,
+    pub diagnostics: Vec<Diagnostic>,
     thing_stack: Vec<Thing>,
+    refs: Vec<PendingRef>,
+    bases: Vec<PendingBase>,
+    abstracts: Vec<Vec<usize>>,
+    /// Index path of the thing changed by the open `patch` block.
+    patch_target: Option<Vec<usize>>,
+    /// Whether placeholders are substituted once the tree is complete, or left to the caller.
+    interpolate: bool,
+    limits: ParseLimits,
+    thing_count: usize,
+    prop_count: usize,
+}
+
+/// Parse state that only depends on the tokens read so far, everything that needs the finished
+/// tree is left to [`Parser`].
+#[derive(Debug, Clone)]
+struct Reader {
+    enums: HashMap<String, EnumDef>,
+    version: Option<Version>,
+    consts: Vec<HashMap<String, PropValue>>,
+    /// Names of the open things, innermost last.
+    open_things: Vec<String>,
+    /// Names declared so far at the top level and in each open thing.
+    siblings: Vec<HashSet<String>>,
+    /// Child names of the closed things by path, only kept with `unique_names` so that patch
+    /// blocks can check the children they add against the existing ones.
+    closed_children: HashMap<String, HashSet<String>>,
+    /// Annotations read so far that belong to the next thing or prop declaration.
+    annotations: Vec<(Annotation, TokenInfo)>,
+    /// Doc comment lines read so far, dropped unless a thing or prop declaration follows.
+    docs: Vec<(String, TokenInfo)>,
+    diagnostics: Vec<Diagnostic>,
+    /// Open `if` blocks whose condition held, with the thing depth they were opened at.
+    conditionals: Vec<(usize, TokenInfo)>,
+    /// Whether the outermost open block is a `patch` block rather than a thing.
+    patching: bool,
+    flags: HashSet<String>,
+    block_keywords: HashSet<String>,
+    prop_types: PropTypes,
+    limits: ParseLimits,
+    thing_count: usize,
+    prop_count: usize,
+    expr_depth: Cell<usize>,
+    unique_names: bool,
+    require_type_annotations: bool,
+    env: Rc<dyn Environment>,
+    events: VecDeque<Event>,
+}
+
+/// Parses tokens into a stream of [`Event`]s without building a tree. Values are reported as
+/// declared: `extends` isn't applied, `${...}` placeholders aren't substituted and refs aren't
+/// checked, since all of these need the whole tree. The stream ends after an [`Event::Error`].
+pub struct PullParser<I: Iterator<Item = (Token, TokenInfo)>> {
+    reader: Reader,
+    tokens: Peekable<CheckedTokens<I>>,
+    stream_error: Rc<RefCell<Option<ParseError>>>,
+    started: bool,
+    done: bool,
+}
+
+/// Ends the token stream at a lexer error or once the token limit is exceeded, leaving the error
+/// to replace whatever the parser made of the truncated input.
+struct CheckedTokens<I> {
+    tokens: I,
+    num_tokens: usize,
+    limits: ParseLimits,
+    error: Rc<RefCell<Option<ParseError>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParserBuilder {
+    reader: Reader,
+    things: Things,
+    templates: HashMap<String, Thing>,
+    interpolate: bool,
 }
 
 impl ParseError {
@@ -23,340 +208,4431 @@ impl ParseError {
         return Self {
             token_info: token_info,
             message: message.into(),
+            kind: ParseErrorKind::Syntax,
+        };
+    }
+
+    pub fn limit(token_info: TokenInfo, limit: Limit, limits: &ParseLimits) -> Self {
+        return Self {
+            token_info: token_info,
+            message: limits.message(limit),
+            kind: ParseErrorKind::LimitExceeded(limit),
         };
     }
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "line {}:{} - {}",
+            self.token_info.line, self.token_info.col, self.message
+        );
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Default for Parser {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
 impl Parser {
     pub fn new() -> Self {
         return Self {
-            things: HashMap::new(),
+            things: Things::new(),
+            enums: HashMap::new(),
+            constants: HashMap::new(),
+            templates: HashMap::new(),
+            version: None,
+            diagnostics: Vec::new(),
             thing_stack: Vec::new(),
+            refs: Vec::new(),
+            bases: Vec::new(),
+            abstracts: Vec::new(),
+            patch_target: None,
+            interpolate: true,
+            limits: ParseLimits::default(),
+            thing_count: 0,
+            prop_count: 0,
+        };
+    }
+
+    pub fn build() -> ParserBuilder {
+        return ParserBuilder {
+            reader: Reader::new(),
+            things: Things::new(),
+            templates: HashMap::new(),
+            interpolate: true,
         };
     }
 
     pub fn from_tokens(
-        mut tokens: impl Iterator<Item = (Token, TokenInfo)>,
+        tokens: impl Iterator<Item = (Token, TokenInfo)>,
     ) -> Result<Self, ParseError> {
-        let mut parser = Self::new();
-
-        while let Some(item) = tokens.next() {
-            match parser.parse_token(item, &mut tokens) {
-                Ok(()) => {}
-                Err(err) => return Err(err),
-            }
-        }
-
-        if !parser.thing_stack.is_empty() {
-            let thing = parser.thing_stack.pop().unwrap();
-            return Err(ParseError::new(
-                TokenInfo::new(0, 0),
-                "Token `".to_owned() + &thing.name + "` is missing a closing brace `}`",
-            ));
-        }
-        return Ok(parser);
+        return Self::build().parse(tokens);
     }
 
-    fn add_thing(&mut self, name_literal: &String) {
-        self.thing_stack
-            .push(Thing::new(strip_quotes(&name_literal).to_string()));
+    /// Builds the tree from an event stream, then applies inheritance, substitutes placeholders
+    /// and checks refs.
+    pub fn from_events<I>(events: PullParser<I>) -> Result<Self, ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        return Self::from_events_over(Parser::new(), events);
     }
 
-    fn parse_token<I>(
-        &mut self,
-        (token, token_info): (Token, TokenInfo),
-        iter: &mut I,
-    ) -> Result<(), ParseError>
+    /// Like [`Parser::from_events`], continuing `parser` with the things and templates of an
+    /// earlier parse already in place.
+    fn from_events_over<I>(
+        mut parser: Parser,
+        mut events: PullParser<I>,
+    ) -> Result<Self, ParseError>
     where
         I: Iterator<Item = (Token, TokenInfo)>,
     {
-        match token.kind {
-            TokenKind::Word => match token.literal.as_str() {
-                "thing" => return self.parse_thing((token, token_info), iter),
-                "int" | "float" | "bool" | "string" => {
-                    return self.parse_prop((token, token_info), iter)
-                }
-                _ => {
-                    return Err(ParseError::new(token_info, "Unexpected token"));
+        while let Some(event) = events.next() {
+            match event {
+                Event::StartThing {
+                    name,
+                    kind,
+                    base,
+                    is_abstract,
+                    annotations,
+                    doc,
+                    ..
+                } => {
+                    let mut thing = Thing::new(name);
+                    thing.kind = kind;
+                    thing.annotations = annotations;
+                    thing.doc = doc;
+                    parser.start_thing(thing, base, is_abstract);
                 }
-            },
-            TokenKind::Symbol => match token.literal.as_str() {
-                "}" => {
-                    let Some(thing) = self.thing_stack.pop() else {
-                        return Err(ParseError::new(token_info, "Unexpected symbol: `}`"));
+                Event::Prop {
+                    name,
+                    value,
+                    unit,
+                    annotations,
+                    doc,
+                    span,
+                } => {
+                    // the reader hasn't moved past the declaration yet, so its constants are
+                    // the ones in scope for the placeholders. Files older than interpolation
+                    // keep `${` as literal text.
+                    let consts = match &value {
+                        PropValue::String(val)
+                            if val.contains("${")
+                                && events.reader.allows(Feature::Interpolation) =>
+                        {
+                            Some(events.reader.visible_consts())
+                        }
+                        _ => None,
                     };
 
-                    match self.thing_stack.last_mut() {
-                        Some(parent) => parent.add_thing(thing),
-                        None => self.things.insert(thing.name.clone(), thing),
-                    };
-                }
-                _ => {
-                    return Err(ParseError::new(token_info, "Unexpected token"));
+                    let mut prop = Prop::new(name, value);
+                    prop.unit = unit;
+                    prop.annotations = annotations;
+                    prop.doc = doc;
+                    parser.add_prop(prop, span.end, consts);
                 }
-            },
-            _ => {
-                return Err(ParseError::new(token_info, "Unexpected token"));
+                Event::EndThing => parser.end_thing(),
+                Event::StartPatch { path, span } => parser.start_patch(&path, span.start)?,
+                Event::Unset { name, span } => parser.unset_prop(&name, span.start)?,
+                Event::RemoveThing { name, span } => parser.remove_child(&name, span.start)?,
+                Event::EndPatch => parser.patch_target = None,
+                Event::Error(err) => return Err(err),
             }
         }
-        return Ok(());
-    }
 
-    fn parse_thing<I>(
-        &mut self,
-        (_, token_info): (Token, TokenInfo),
-        iter: &mut I,
-    ) -> Result<(), ParseError>
-    where
-        I: Iterator<Item = (Token, TokenInfo)>,
-    {
-        let Some((token_p1, token_p1_info)) = iter.next() else {
-            return Err(ParseError::new(token_info, "Expected String name after keyword `thing`"));
-        };
+        let reader = events.reader;
+        parser.constants = reader.visible_consts();
+        parser.enums = reader.enums;
+        parser.version = reader.version;
+        parser.diagnostics = reader.diagnostics;
+        parser.limits = reader.limits;
+        parser.thing_count = reader.thing_count;
+        parser.prop_count = reader.prop_count;
 
-        if token_p1.kind != TokenKind::String {
-            return Err(ParseError::new(
-                token_p1_info,
-                "Expected String name after keyword `thing`",
-            ));
+        parser.resolve_inheritance()?;
+        if parser.interpolate {
+            parser.interpolate_strings()?;
         }
+        parser.check_refs().map_err(|(err, _)| err)?;
+        return Ok(parser);
+    }
 
-        let Some((token_p2, token_p2_info)) = iter.next() else {
-            return Err(ParseError::new(token_p1_info, "Expected `{` after thing name"));
-        };
-
-        if token_p2.kind != TokenKind::Symbol || token_p2.literal != "{" {
-            return Err(ParseError::new(
-                token_p2_info,
-                "Expected `{` after thing name",
-            ));
+    fn start_thing(&mut self, thing: Thing, base: Option<(String, TokenInfo)>, is_abstract: bool) {
+        self.thing_stack.push(thing);
+        if let Some((base, base_info)) = base {
+            self.bases.push(PendingBase {
+                path: self.stack_path(),
+                base: base,
+                token_info: base_info,
+            });
         }
+        if is_abstract {
+            self.abstracts.push(self.stack_path());
+        }
+    }
 
-        self.add_thing(&token_p1.literal);
-        return Ok(());
+    fn end_thing(&mut self) {
+        let thing = self.thing_stack.pop().unwrap();
+        if self.thing_stack.is_empty() && self.patch_target.is_none() {
+            self.things.push(thing);
+        } else {
+            self.current_thing().add_thing(thing);
+        }
     }
 
-    fn parse_prop<I>(
-        &mut self,
-        (token, token_info): (Token, TokenInfo),
-        iter: &mut I,
-    ) -> Result<(), ParseError>
-    where
-        I: Iterator<Item = (Token, TokenInfo)>,
-    {
+    /// The innermost open thing, or the patched thing directly inside a `patch` block.
+    fn current_thing(&mut self) -> &mut Thing {
         if self.thing_stack.is_empty() {
-            return Err(ParseError::new(
-                token_info,
-                "Unexpected prop definition outside of thing",
-            ));
+            let target = self.patch_target.clone().unwrap();
+            return self.thing_at_mut(&target).unwrap();
         }
+        return self.thing_stack.last_mut().unwrap();
+    }
 
-        let Some((token_name, token_name_info)) = iter.next() else {
+    /// Opens a `patch` block. Patches change things as declared so far: `extends` is applied
+    /// once the whole tree is parsed, so props inherited from a base can't be unset.
+    fn start_patch(&mut self, path: &str, token_info: TokenInfo) -> Result<(), ParseError> {
+        let Some(target) = self.locate(path) else {
             return Err(ParseError::new(
                 token_info,
-                "Expected name after prop type",
+                "Unknown patch target `".to_owned() + path + "`",
             ));
         };
+        self.patch_target = Some(target);
+        return Ok(());
+    }
 
-        if token_name.kind != TokenKind::Word {
+    fn unset_prop(&mut self, name: &str, token_info: TokenInfo) -> Result<(), ParseError> {
+        let owner = self.patch_target.clone().unwrap();
+        if self
+            .thing_at_mut(&owner)
+            .unwrap()
+            .props
+            .remove(name)
+            .is_none()
+        {
             return Err(ParseError::new(
-                token_name_info,
-                "Expected name after prop type",
+                token_info,
+                "Prop `".to_owned() + name + "` doesn't exist in `" + &self.name_path(&owner) + "`",
             ));
         }
+        self.refs
+            .retain(|pending| pending.owner != owner || pending.prop != name);
+        return Ok(());
+    }
 
-        let prop_name = token_name.literal;
-
-        let Some((token_eq, token_eq_info)) = iter.next() else {
+    /// Removes the first child named `name` of the patched thing.
+    fn remove_child(&mut self, name: &str, token_info: TokenInfo) -> Result<(), ParseError> {
+        let parent = self.patch_target.clone().unwrap();
+        let things = &mut self.thing_at_mut(&parent).unwrap().things;
+        let Some(index) = things.iter().position(|thing| thing.name == name) else {
             return Err(ParseError::new(
-                token_name_info,
-                "Expected `=` symbol prop name",
+                token_info,
+                "Thing `".to_owned()
+                    + name
+                    + "` doesn't exist in `"
+                    + &self.name_path(&parent)
+                    + "`",
             ));
         };
+        things.remove_at(index);
+        self.forget_child(&parent, index);
+        return Ok(());
+    }
 
-        if token_eq.kind != TokenKind::Symbol || token_eq.literal != "=" {
-            return Err(ParseError::new(
-                token_eq_info,
-                "Expected `=` symbol prop name",
-            ));
-        }
-
-        let Some((token_val, token_val_info)) = iter.next() else {
-            return Err(ParseError::new(
-                token_eq_info,
-                "Expected value after prop declaration",
-            ));
-        };
+    /// Drops the pending entries of the just removed child `index` of the thing at `parent`, and
+    /// moves those of its later siblings up by one.
+    fn forget_child(&mut self, parent: &[usize], index: usize) {
+        let removed = [parent, &[index]].concat();
+        self.refs
+            .retain(|pending| !pending.owner.starts_with(&removed));
+        self.bases
+            .retain(|pending| !pending.path.starts_with(&removed));
+        self.abstracts.retain(|path| !path.starts_with(&removed));
 
-        let prop = match token.literal.as_str() {
-            "int" => Prop::int_from_literal(prop_name, token_val.literal),
-            "float" => Prop::float_from_literal(prop_name, token_val.literal),
-            "bool" => Prop::bool_from_literal(prop_name, token_val.literal),
-            "string" => Prop::string_from_literal(prop_name, token_val.literal),
-            _ => {
-                return Err(ParseError::new(
-                    token_info,
-                    "Unexpected prop type `".to_owned() + &token.literal + "`",
-                ));
+        let paths = self.refs.iter_mut().map(|pending| &mut pending.owner);
+        let paths = paths.chain(self.bases.iter_mut().map(|p| &mut p.path));
+        for path in paths.chain(self.abstracts.iter_mut()) {
+            if path.len() > parent.len() && path.starts_with(parent) && path[parent.len()] > index {
+                path[parent.len()] -= 1;
             }
-        };
-
-        if prop.value == PropValue::Err {
-            return Err(ParseError::new(
-                token_val_info,
-                "Unable to parse prop value matching declared prop type",
-            ));
         }
-
-        self.thing_stack.last_mut().unwrap().add_prop(prop);
-        return Ok(());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{core::PropValue, lexer::Lexer};
+    /// Adds a prop to the innermost open thing, recording it for the passes that run once the
+    /// tree is complete. `consts` are the constants visible at the declaration, `None` when the
+    /// value isn't interpolated.
+    fn add_prop(
+        &mut self,
+        mut prop: Prop,
+        token_val_info: TokenInfo,
+        consts: Option<HashMap<String, PropValue>>,
+    ) {
+        let owner = self.stack_path();
+        prop.token_info = Some(token_val_info);
+        if self.current_thing().props.contains_key(&prop.name) {
+            self.refs
+                .retain(|pending| pending.owner != owner || pending.prop != prop.name);
+        }
 
-    fn populate_parser(source: &str) -> Result<Parser, ParseError> {
-        let lexer = Lexer::new(source);
-        return Parser::from_tokens(lexer);
+        match &prop.value {
+            PropValue::Ref(target) => self.refs.push(PendingRef {
+                owner: owner,
+                prop: prop.name.clone(),
+                target: target.clone(),
+                token_info: token_val_info,
+            }),
+            PropValue::String(val) => {
+                if let Some(consts) = consts {
+                    prop.template = Some(Rc::new(Template {
+                        source: val.clone(),
+                        consts: consts,
+                        token_info: token_val_info,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        self.current_thing().add_prop(prop);
     }
 
-    #[test]
-    fn parses_a_thing() {
-        let parser = populate_parser(r#"thing "MyThing" {} "#).unwrap();
-        assert_eq!(parser.things.len(), 1);
-        assert_eq!(parser.things.get("MyThing").unwrap().name, "MyThing");
+    pub fn things_of_kind(&self, kind: &str) -> Vec<&Thing> {
+        let mut found = Vec::new();
+        for thing in &self.things {
+            thing.collect_kind(kind, &mut found);
+        }
+        return found;
     }
 
-    #[test]
-    fn parses_sibbling_things() {
-        let parser = populate_parser(
-            r#"
-            thing "MyThing" {}
-            thing "MyThing2" {}
-        "#,
-        )
-        .unwrap();
-        assert_eq!(parser.things.len(), 2);
+    /// Looks up a thing by its `/` separated path from the top level, e.g. `Hello/World/Inner`.
+    /// Each segment picks the first thing with that name.
+    pub fn resolve_path(&self, path: &str) -> Option<&Thing> {
+        let mut segments = path.split('/');
+        let mut thing = self.things.get(segments.next()?)?;
+        for segment in segments {
+            thing = thing.get_thing(segment)?;
+        }
+        return Some(thing);
     }
 
-    #[test]
-    fn parses_nested_things() {
-        let parser = populate_parser(
-            r#"
-            thing "MyThing" {
-                thing "Nested" {}
-            } 
-        "#,
-        )
-        .unwrap();
-        assert_eq!(parser.things.len(), 1);
-        assert_eq!(parser.things.get("MyThing").unwrap().num_things(), 1);
+    /// Returns the thing a `ref` prop points to, or `None` for any other prop value.
+    pub fn resolve_ref(&self, value: &PropValue) -> Option<&Thing> {
+        let PropValue::Ref(path) = value else {
+            return None;
+        };
+        return self.resolve_path(path);
     }
 
-    #[test]
-    fn thing_as_last_token_leads_to_error() {
-        assert!(populate_parser(r#"thing"#).is_err());
+    /// Index path of the innermost open thing, i.e. where it will be once it's closed. Inside a
+    /// `patch` block that is below the patched thing.
+    fn stack_path(&self) -> Vec<usize> {
+        let mut path = self.patch_target.clone().unwrap_or_default();
+        if !self.thing_stack.is_empty() {
+            path.push(match &path[..] {
+                [] => self.things.len(),
+                target => self.thing_at(target).unwrap().things.len(),
+            });
+        }
+        for window in self.thing_stack.windows(2) {
+            path.push(window[0].things.len());
+        }
+        return path;
     }
 
-    #[test]
-    fn thing_not_followed_by_string_leads_to_error() {
-        let err = populate_parser(r#"thing 12"#).unwrap_err();
-        assert_eq!(err.token_info, TokenInfo::new(0, 6));
+    fn locate(&self, path: &str) -> Option<Vec<usize>> {
+        let mut indices = Vec::new();
+        let mut things = &self.things;
+        for segment in path.split('/') {
+            let index = things.iter().position(|thing| thing.name == segment)?;
+            indices.push(index);
+            things = &things.at(index).unwrap().things;
+        }
+        return Some(indices);
     }
 
-    #[test]
-    fn thing_and_name_without_opening_brace_leads_to_error() {
-        let err = populate_parser(r#"thing "Name" a"#).unwrap_err();
-        assert_eq!(err.token_info, TokenInfo::new(0, 13));
+    fn name_path(&self, path: &[usize]) -> String {
+        let mut names = Vec::new();
+        for len in 1..=path.len() {
+            names.push(self.thing_at(&path[..len]).unwrap().name.clone());
+        }
+        return names.join("/");
     }
 
-    #[test]
-    fn thing_without_closing_brace_leads_to_error() {
-        let err = populate_parser(r#"thing "Name" {"#).unwrap_err();
-        assert_eq!(err.token_info, TokenInfo::new(0, 0));
-    }
+    /// Checks every ref in the tree again, e.g. once other things were merged into it or removed
+    /// from it. The error comes with the origin of the failing prop.
+    pub(crate) fn check_all_refs(&mut self) -> Result<(), (ParseError, Option<String>)> {
+        let mut refs: Vec<PendingRef> = (self.props_by_path().into_iter())
+            .filter_map(|(owner, prop)| match &prop.value {
+                PropValue::Ref(target) => Some(PendingRef {
+                    owner: owner,
+                    prop: prop.name.clone(),
+                    target: target.clone(),
+                    token_info: prop.token_info.unwrap_or(TokenInfo::new(0, 0)),
+                }),
+                _ => None,
+            })
+            .collect();
+        refs.sort_by(|a, b| {
+            let (a_info, b_info) = (a.token_info, b.token_info);
+            return (a_info.line, a_info.col, &a.owner).cmp(&(b_info.line, b_info.col, &b.owner));
+        });
+        self.refs = refs;
 
-    #[test]
-    fn parses_int_prop() {
-        let parser = populate_parser(r#" thing "Name" { int prop = 12 } "#).unwrap();
-        let thing = parser.things.get("Name").unwrap();
-        let prop = thing.props.get("prop").unwrap();
-        assert_eq!(prop.value, PropValue::Int(12))
+        return self.check_refs().map_err(|(err, pending)| {
+            let owner = self.thing_at(&pending.owner).unwrap();
+            (err, owner.props.get(&pending.prop).unwrap().origin.clone())
+        });
     }
 
-    #[test]
-    fn parses_float_prop() {
-        let parser = populate_parser(r#" thing "Name" { float prop = 12.1 } "#).unwrap();
-        let thing = parser.things.get("Name").unwrap();
-        let prop = thing.props.get("prop").unwrap();
-        assert_eq!(prop.value, PropValue::Float(12.1))
-    }
+    /// Returns the failing ref along with the error.
+    fn check_refs(&self) -> Result<(), (ParseError, &PendingRef)> {
+        for pending in &self.refs {
+            if self.resolve_path(&pending.target).is_none() {
+                let err = ParseError::new(
+                    pending.token_info,
+                    "Unresolved reference `".to_owned() + &pending.target + "`",
+                );
+                return Err((err, pending));
+            }
+        }
 
-    #[test]
-    fn parses_bool_prop() {
-        let parser = populate_parser(r#" thing "Name" { bool prop = true } "#).unwrap();
-        let thing = parser.things.get("Name").unwrap();
-        let prop = thing.props.get("prop").unwrap();
-        assert_eq!(prop.value, PropValue::Bool(true))
-    }
+        let mut edges = HashMap::<&[usize], Vec<&PendingRef>>::new();
+        for pending in &self.refs {
+            edges.entry(&pending.owner).or_default().push(pending);
+        }
 
-    #[test]
-    fn parses_string_prop() {
-        let parser = populate_parser(r#" thing "Name" { string prop = "Hello" } "#).unwrap();
-        let thing = parser.things.get("Name").unwrap();
-        let prop = thing.props.get("prop").unwrap();
-        assert_eq!(prop.value, PropValue::String("Hello".to_string()));
+        let mut done = HashMap::<Vec<usize>, bool>::new();
+        for pending in &self.refs {
+            if !done.contains_key(&pending.owner) {
+                self.check_ref_cycles(pending.owner.clone(), &edges, &mut done)?;
+            }
+        }
+        return Ok(());
     }
 
-    #[test]
-    fn top_level_prop_definition_results_in_error() {
-        let err = populate_parser(r#"string prop = "Hello""#).unwrap_err();
-        assert_eq!(err.message, "Unexpected prop definition outside of thing");
-    }
+    /// Following refs from thing to thing may never lead back to a thing whose refs are still
+    /// being followed. Refs resolve lazily, so children and refs of a thing to itself are fine.
+    /// Walks with an explicit stack, since ref chains can be as long as the file allows.
+    fn check_ref_cycles<'a>(
+        &self,
+        start: Vec<usize>,
+        edges: &HashMap<&[usize], Vec<&'a PendingRef>>,
+        done: &mut HashMap<Vec<usize>, bool>,
+    ) -> Result<(), (ParseError, &'a PendingRef)> {
+        done.insert(start.clone(), false);
+        // each entry is a thing whose refs are being followed and the index of its next ref
+        let mut stack = vec![(start, 0)];
+        while let Some((path, next)) = stack.last_mut() {
+            let Some(pending) = edges.get(path.as_slice()).and_then(|refs| refs.get(*next)) else {
+                done.insert(path.clone(), true);
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+
+            let target = self.locate(&pending.target).unwrap();
+            if target == *path {
+                continue;
+            }
+
+            match done.get(&target) {
+                Some(true) => {}
+                Some(false) => {
+                    let err = ParseError::new(
+                        pending.token_info,
+                        "Reference `".to_owned() + &pending.target + "` forms a cycle",
+                    );
+                    return Err((err, pending));
+                }
+                None => {
+                    done.insert(target.clone(), false);
+                    stack.push((target, 0));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Handles `start` after every entry it depends on, each entry once. Uses an explicit stack
+    /// rather than recursion, so long dependency chains can't overflow the call stack.
+    /// `cycle` builds the error for an entry that is reached again through its own dependencies.
+    fn visit_in_order(
+        &mut self,
+        start: usize,
+        states: &mut [Option<VisitState>],
+        dependencies: impl Fn(&Self, usize) -> Result<Vec<usize>, ParseError>,
+        mut visit: impl FnMut(&mut Self, usize) -> Result<(), ParseError>,
+        cycle: impl Fn(usize) -> ParseError,
+    ) -> Result<(), ParseError> {
+        let mut stack = vec![start];
+        while let Some(&index) = stack.last() {
+            if states[index] == Some(VisitState::Done) {
+                stack.pop();
+                continue;
+            }
+            states[index] = Some(VisitState::Visiting);
+
+            let dependencies = dependencies(self, index)?;
+            let next = dependencies
+                .into_iter()
+                .find(|dependency| states[*dependency] != Some(VisitState::Done));
+            match next {
+                Some(dependency) if states[dependency] == Some(VisitState::Visiting) => {
+                    return Err(cycle(dependency));
+                }
+                Some(dependency) => stack.push(dependency),
+                None => {
+                    visit(self, index)?;
+                    states[index] = Some(VisitState::Done);
+                    stack.pop();
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn thing_at(&self, path: &[usize]) -> Option<&Thing> {
+        let (first, rest) = path.split_first()?;
+        let mut thing = self.things.at(*first)?;
+        for index in rest {
+            thing = thing.things.at(*index)?;
+        }
+        return Some(thing);
+    }
+
+    fn thing_at_mut(&mut self, path: &[usize]) -> Option<&mut Thing> {
+        let (first, rest) = path.split_first()?;
+        let mut thing = self.things.at_mut(*first)?;
+        for index in rest {
+            thing = thing.things.at_mut(*index)?;
+        }
+        return Some(thing);
+    }
+
+    fn resolve_inheritance(&mut self) -> Result<(), ParseError> {
+        let pending = std::mem::take(&mut self.bases);
+        let mut by_root = HashMap::<usize, Vec<usize>>::new();
+        for (index, base) in pending.iter().enumerate() {
+            by_root.entry(base.path[0]).or_default().push(index);
+        }
+
+        let mut states = vec![None; pending.len()];
+        for index in 0..pending.len() {
+            self.visit_in_order(
+                index,
+                &mut states,
+                |parser, index| parser.base_dependencies(index, &pending, &by_root),
+                |parser, index| parser.inherit(&pending[index]),
+                |index| {
+                    ParseError::new(
+                        pending[index].token_info,
+                        "Inheritance of `".to_owned() + &pending[index].base + "` forms a cycle",
+                    )
+                },
+            )?;
+        }
+
+        let mut abstracts = std::mem::take(&mut self.abstracts);
+        let is_template = |owner: &[usize]| abstracts.iter().any(|path| owner.starts_with(path));
+        self.refs.retain(|pending| !is_template(&pending.owner));
+
+        // last first, so removing a template never shifts the path of one still to be removed
+        abstracts.sort_by(|a, b| b.cmp(a));
+        for path in &abstracts {
+            let name_path = self.name_path(path);
+            let (index, parent) = path.split_last().unwrap();
+            let template = if parent.is_empty() {
+                self.things.remove_at(*index)
+            } else {
+                self.thing_at_mut(parent).unwrap().things.remove_at(*index)
+            };
+            self.templates.insert(name_path, template);
+            self.forget_child(parent, *index);
+        }
+        return Ok(());
+    }
+
+    /// The pending bases inside the base thing of `pending[index]`, which have to be applied
+    /// first so that the copied subtree is already complete. `by_root` groups the pending bases
+    /// by their top level thing.
+    fn base_dependencies(
+        &self,
+        index: usize,
+        pending: &[PendingBase],
+        by_root: &HashMap<usize, Vec<usize>>,
+    ) -> Result<Vec<usize>, ParseError> {
+        let Some(base_path) = self.locate_base(&pending[index])? else {
+            return Ok(Vec::new());
+        };
+        let dependencies = by_root
+            .get(&base_path[0])
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|other| pending[*other].path.starts_with(&base_path))
+            .collect();
+        return Ok(dependencies);
+    }
+
+    /// Path of the thing `current` extends, `None` for a template of an earlier parse, which is
+    /// no longer in the tree and already complete.
+    fn locate_base(&self, current: &PendingBase) -> Result<Option<Vec<usize>>, ParseError> {
+        if let Some(path) = self.locate(&current.base) {
+            return Ok(Some(path));
+        }
+        if self.templates.contains_key(&current.base) {
+            return Ok(None);
+        }
+        return Err(ParseError::new(
+            current.token_info,
+            "Unknown base thing `".to_owned() + &current.base + "`",
+        ));
+    }
+
+    /// Applies one `extends` declaration.
+    fn inherit(&mut self, current: &PendingBase) -> Result<(), ParseError> {
+        let base_path = self.locate_base(current)?;
+        let base = match &base_path {
+            Some(path) => self.thing_at(path).unwrap().clone(),
+            None => self.templates.get(&current.base).unwrap().clone(),
+        };
+        let derived = self.thing_at_mut(&current.path).unwrap();
+        let mut inherited = Vec::new();
+        let copied = Self::merge_base(derived, &base, &mut Vec::new(), &mut inherited);
+
+        self.thing_count += copied;
+        self.prop_count += inherited.len();
+        for (count, limit) in [
+            (self.thing_count, Limit::Things),
+            (self.prop_count, Limit::Props),
+        ] {
+            if count > self.limits.max(limit) {
+                return Err(ParseError::limit(current.token_info, limit, &self.limits));
+            }
+        }
+
+        // inherited props keep their deferred ref checks, now in the derived thing
+        for (rel_path, prop) in inherited {
+            let to = [current.path.as_slice(), &rel_path].concat();
+            let copies: Vec<PendingRef> = match &base_path {
+                Some(base_path) => {
+                    let from = [base_path.as_slice(), &rel_path].concat();
+                    self.refs
+                        .iter()
+                        .filter(|pending| pending.owner == from && pending.prop == prop)
+                        .map(|pending| PendingRef {
+                            owner: to.clone(),
+                            ..pending.clone()
+                        })
+                        .collect()
+                }
+                // refs of an earlier template were never checked, report them at the `extends`
+                None => match &self.thing_at(&to).unwrap().props.get(&prop).unwrap().value {
+                    PropValue::Ref(target) => vec![PendingRef {
+                        owner: to.clone(),
+                        prop: prop,
+                        target: target.clone(),
+                        token_info: current.token_info,
+                    }],
+                    _ => Vec::new(),
+                },
+            };
+            self.refs.extend(copies);
+        }
+        return Ok(());
+    }
+
+    /// Deep-copies the props and children of `base` that `thing` doesn't override, recording the
+    /// location of every copied prop relative to `thing`. The n-th child of a name in `base` is
+    /// merged into the n-th child of that name in `thing`, anonymous children are always copied.
+    /// Returns the number of copied things.
+    fn merge_base(
+        thing: &mut Thing,
+        base: &Thing,
+        rel_path: &mut Vec<usize>,
+        inherited: &mut Vec<(Vec<usize>, String)>,
+    ) -> usize {
+        let mut copied = 0;
+        if thing.kind.is_none() {
+            thing.kind = base.kind.clone();
+        }
+        if thing.doc.is_none() {
+            thing.doc = base.doc.clone();
+        }
+
+        for annotation in &base.annotations {
+            if thing.annotation(&annotation.name).is_none() {
+                thing.annotations.push(annotation.clone());
+            }
+        }
+
+        for (name, prop) in &base.props {
+            if !thing.props.contains_key(name) {
+                thing.add_prop(prop.clone());
+                inherited.push((rel_path.clone(), name.clone()));
+            }
+        }
+
+        let mut seen = HashMap::<&str, usize>::new();
+        for child in &base.things {
+            let occurrence = seen.entry(&child.name).or_default();
+            let existing = match child.name.is_empty() {
+                true => None,
+                false => (thing.things.iter())
+                    .enumerate()
+                    .filter(|(_, other)| other.name == child.name)
+                    .nth(*occurrence)
+                    .map(|(index, _)| index),
+            };
+            *occurrence += 1;
+
+            match existing {
+                Some(index) => {
+                    rel_path.push(index);
+                    let existing = thing.things.at_mut(index).unwrap();
+                    copied += Self::merge_base(existing, child, rel_path, inherited);
+                }
+                None => {
+                    rel_path.push(thing.things.len());
+                    let mut copy = Thing::new(child.name.clone());
+                    copied += 1 + Self::merge_base(&mut copy, child, rel_path, inherited);
+                    thing.add_thing(copy);
+                }
+            }
+            rel_path.pop();
+        }
+        return copied;
+    }
+
+    /// Substitutes the placeholders of every string prop in the tree declared with `${...}`,
+    /// starting over from the declared strings.
+    pub(crate) fn interpolate_strings(&mut self) -> Result<(), ParseError> {
+        let pending = self.pending_interpolations();
+        let mut by_prop = HashMap::<(&[usize], &str), usize>::new();
+        for (index, current) in pending.iter().enumerate() {
+            by_prop
+                .entry((&current.owner, &current.prop))
+                .or_insert(index);
+        }
+
+        let mut states = vec![None; pending.len()];
+        for index in 0..pending.len() {
+            self.visit_in_order(
+                index,
+                &mut states,
+                |parser, index| parser.interpolation_dependencies(&pending[index], &by_prop),
+                |parser, index| parser.interpolate_string(&pending[index]),
+                |index| {
+                    ParseError::new(
+                        pending[index].template.token_info,
+                        "String interpolation of `".to_owned()
+                            + &pending[index].prop
+                            + "` forms a cycle",
+                    )
+                },
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// Every prop in the tree with the path of its thing.
+    fn props_by_path(&self) -> Vec<(Vec<usize>, &Prop)> {
+        let mut props = Vec::new();
+        let mut stack: Vec<(Vec<usize>, &Thing)> = (self.things.iter())
+            .enumerate()
+            .map(|(index, thing)| (vec![index], thing))
+            .collect();
+        while let Some((path, thing)) = stack.pop() {
+            for (index, child) in thing.things.iter().enumerate() {
+                stack.push(([path.as_slice(), &[index]].concat(), child));
+            }
+            props.extend(thing.props.values().map(|prop| (path.clone(), prop)));
+        }
+        return props;
+    }
+
+    /// The string props with placeholders, in declaration order.
+    fn pending_interpolations(&self) -> Vec<PendingInterpolation> {
+        let mut pending: Vec<PendingInterpolation> = (self.props_by_path().into_iter())
+            .filter_map(|(owner, prop)| {
+                Some(PendingInterpolation {
+                    owner: owner,
+                    prop: prop.name.clone(),
+                    template: prop.template.clone()?,
+                })
+            })
+            .collect();
+
+        pending.sort_by(|a, b| {
+            let (a_info, b_info) = (a.template.token_info, b.template.token_info);
+            return (a_info.line, a_info.col, &a.owner).cmp(&(b_info.line, b_info.col, &b.owner));
+        });
+        return pending;
+    }
+
+    /// Path of the thing whose prop `name` a placeholder in the thing at `owner` refers to: the
+    /// owner itself or its closest ancestor declaring it. `None` leaves it to the constants.
+    fn placeholder_owner<'a>(&self, owner: &'a [usize], name: &str) -> Option<&'a [usize]> {
+        let mut path = owner;
+        while !path.is_empty() {
+            if self.thing_at(path).unwrap().props.contains_key(name) {
+                return Some(path);
+            }
+            path = &path[..path.len() - 1];
+        }
+        return None;
+    }
+
+    /// The pending strings that `current` interpolates, to be substituted first. `by_prop` finds
+    /// pending strings by their owner and prop.
+    fn interpolation_dependencies(
+        &self,
+        current: &PendingInterpolation,
+        by_prop: &HashMap<(&[usize], &str), usize>,
+    ) -> Result<Vec<usize>, ParseError> {
+        let parts = split_template(&current.template.source)
+            .map_err(|message| ParseError::new(current.template.token_info, message))?;
+
+        let mut dependencies = Vec::new();
+        for part in parts {
+            let TemplatePart::Name(name) = part else {
+                continue;
+            };
+            let Some(path) = self.placeholder_owner(&current.owner, name) else {
+                continue;
+            };
+            dependencies.extend(by_prop.get(&(path, name)));
+        }
+        return Ok(dependencies);
+    }
+
+    /// Substitutes the placeholders of one pending string, once the strings it refers to are
+    /// done. Names are looked up in the owning thing, then its ancestors, then constants.
+    fn interpolate_string(&mut self, current: &PendingInterpolation) -> Result<(), ParseError> {
+        let parts = split_template(&current.template.source)
+            .map_err(|message| ParseError::new(current.template.token_info, message))?;
+
+        let mut result = String::new();
+        for part in parts {
+            let name = match part {
+                TemplatePart::Text(text) => {
+                    result.push_str(text);
+                    continue;
+                }
+                TemplatePart::Name(name) => name,
+            };
+
+            let value = match self.placeholder_owner(&current.owner, name) {
+                Some(path) => {
+                    let thing = self.thing_at(path).unwrap();
+                    Some(thing.props.get(name).unwrap().value.to_string())
+                }
+                None => (current.template.consts.get(name)).map(|value| value.to_string()),
+            };
+
+            let Some(value) = value else {
+                return Err(ParseError::new(
+                    current.template.token_info,
+                    "Unknown name `".to_owned() + name + "` in string interpolation",
+                ));
+            };
+            result.push_str(&value);
+        }
+
+        if result.len() > self.limits.max_string_bytes {
+            return Err(ParseError::limit(
+                current.template.token_info,
+                Limit::StringBytes,
+                &self.limits,
+            ));
+        }
+
+        let owner = self.thing_at_mut(&current.owner).unwrap();
+        owner.props.get_mut(&current.prop).unwrap().value = PropValue::String(result);
+        return Ok(());
+    }
+}
+
+impl Reader {
+    fn new() -> Self {
+        return Self {
+            enums: HashMap::new(),
+            version: None,
+            consts: vec![HashMap::new()],
+            open_things: Vec::new(),
+            siblings: vec![HashSet::new()],
+            closed_children: HashMap::new(),
+            annotations: Vec::new(),
+            docs: Vec::new(),
+            diagnostics: Vec::new(),
+            conditionals: Vec::new(),
+            patching: false,
+            flags: HashSet::new(),
+            block_keywords: HashSet::new(),
+            prop_types: PropTypes::new(),
+            limits: ParseLimits::default(),
+            thing_count: 0,
+            prop_count: 0,
+            expr_depth: Cell::new(0),
+            unique_names: false,
+            require_type_annotations: false,
+            env: Rc::new(StdEnvironment),
+            events: VecDeque::new(),
+        };
+    }
+
+    fn parse_token<I>(
+        &mut self,
+        (token, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let is_declaration = match token.kind {
+            TokenKind::Word => {
+                !matches!(token.literal.as_str(), "enum" | "const" | "if" | "else")
+                    && !self.is_patch_statement(&token)
+            }
+            TokenKind::Symbol => token.literal == "@",
+            TokenKind::DocComment => true,
+            _ => false,
+        };
+        if !is_declaration {
+            self.drop_docs();
+        }
+
+        if let Some((annotation, annotation_info)) = self.annotations.first() {
+            if !is_declaration {
+                return Err(ParseError::new(
+                    *annotation_info,
+                    "Annotation `@".to_owned()
+                        + &annotation.name
+                        + "` must be followed by a thing or prop declaration",
+                ));
+            }
+        }
+
+        match token.kind {
+            TokenKind::Word => match token.literal.as_str() {
+                "thing" => return self.parse_thing((token, token_info), iter, false),
+                "abstract" => {
+                    self.require(Feature::Inheritance, token_info)?;
+                    return match iter.next() {
+                        Some((next, next_info)) if self.is_block_keyword(&next) => {
+                            self.parse_thing((next, next_info), iter, true)
+                        }
+                        Some((_, next_info)) => Err(ParseError::new(
+                            next_info,
+                            "Expected keyword `thing` after `abstract`",
+                        )),
+                        None => Err(ParseError::new(
+                            token_info,
+                            "Expected keyword `thing` after `abstract`",
+                        )),
+                    };
+                }
+                "enum" => return self.parse_enum((token, token_info), iter),
+                "patch" if self.open_things.is_empty() => {
+                    return self.parse_patch((token, token_info), iter)
+                }
+                "unset" if self.is_patch_statement(&token) => {
+                    return self.parse_unset((token, token_info), iter)
+                }
+                "remove" if self.is_patch_statement(&token) => {
+                    return self.parse_remove((token, token_info), iter)
+                }
+                "if" => return self.parse_if((token, token_info), iter),
+                "const" => return self.parse_const((token, token_info), iter),
+                ty if self.is_type_keyword(ty) => {
+                    return self.parse_prop((token, token_info), iter)
+                }
+                kw if self.block_keywords.contains(kw) => {
+                    return self.parse_thing((token, token_info), iter, false)
+                }
+                _ => return self.parse_untyped_prop((token, token_info), iter),
+            },
+            TokenKind::Symbol => match token.literal.as_str() {
+                "@" => return self.parse_annotation((token, token_info), iter),
+                "}" => {
+                    if let Some((depth, _)) = self.conditionals.last() {
+                        if *depth == self.open_things.len() {
+                            self.conditionals.pop();
+                            return self.parse_else(iter, true);
+                        }
+                    }
+
+                    let path = self.open_things.join("/");
+                    if self.open_things.pop().is_none() {
+                        return Err(ParseError::new(token_info, "Unexpected symbol: `}`"));
+                    }
+                    self.consts.pop();
+                    let children = self.siblings.pop().unwrap();
+                    if self.unique_names {
+                        self.closed_children
+                            .entry(path)
+                            .or_default()
+                            .extend(children);
+                    }
+                    if self.open_things.is_empty() && self.patching {
+                        self.patching = false;
+                        self.events.push_back(Event::EndPatch);
+                    } else {
+                        self.events.push_back(Event::EndThing);
+                    }
+                }
+                _ => {
+                    return Err(ParseError::new(token_info, "Unexpected token"));
+                }
+            },
+            TokenKind::DocComment => {
+                self.require(Feature::DocComments, token_info)?;
+                self.docs
+                    .push((strip_doc_comment(&token.literal).to_string(), token_info));
+            }
+            _ => {
+                return Err(ParseError::new(token_info, "Unexpected token"));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Reads `if <condition> {`. When the condition holds the block's contents are parsed in
+    /// place, otherwise they are skipped and an `else` branch is considered.
+    fn parse_if<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Conditionals, token_info)?;
+        let (condition, condition_info) = self.parse_expr(iter, None, 0, token_info)?;
+        let Value::Bool(condition) = condition else {
+            return Err(ParseError::new(
+                condition_info,
+                "Condition of `if` must be a `bool`, found `".to_owned()
+                    + &condition.type_name()
+                    + "`",
+            ));
+        };
+
+        let open_info = match iter.next() {
+            Some((token, token_info))
+                if token.kind == TokenKind::Symbol && token.literal == "{" =>
+            {
+                token_info
+            }
+            Some((_, token_info)) => {
+                return Err(ParseError::new(
+                    token_info,
+                    "Expected `{` after `if` condition",
+                ));
+            }
+            None => {
+                return Err(ParseError::new(
+                    condition_info,
+                    "Expected `{` after `if` condition",
+                ));
+            }
+        };
+
+        if condition {
+            self.conditionals.push((self.open_things.len(), open_info));
+            return Ok(());
+        }
+
+        Self::skip_block(iter, open_info)?;
+        return self.parse_else(iter, false);
+    }
+
+    /// Handles an optional `else` after the block of an `if`. Once a branch of the chain has been
+    /// taken every following `else` and `else if` block is skipped.
+    fn parse_else<I>(&mut self, iter: &mut Peekable<I>, taken: bool) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let is_else = match iter.peek() {
+            Some((token, _)) => token.kind == TokenKind::Word && token.literal == "else",
+            None => false,
+        };
+        if !is_else {
+            return Ok(());
+        }
+
+        let (_, else_info) = iter.next().unwrap();
+        if taken {
+            // the condition of a skipped `else if` is never evaluated
+            let open_info = loop {
+                match iter.next() {
+                    Some((token, token_info)) if token.literal == "{" => break token_info,
+                    Some(_) => {}
+                    None => {
+                        return Err(ParseError::new(
+                            else_info,
+                            "Expected `{` or `if` after `else`",
+                        ))
+                    }
+                }
+            };
+            Self::skip_block(iter, open_info)?;
+            return self.parse_else(iter, true);
+        }
+
+        return match iter.next() {
+            Some((token, token_info)) if token.kind == TokenKind::Word && token.literal == "if" => {
+                self.parse_if((token, token_info), iter)
+            }
+            Some((token, token_info))
+                if token.kind == TokenKind::Symbol && token.literal == "{" =>
+            {
+                self.conditionals.push((self.open_things.len(), token_info));
+                Ok(())
+            }
+            Some((_, token_info)) => Err(ParseError::new(
+                token_info,
+                "Expected `{` or `if` after `else`",
+            )),
+            None => Err(ParseError::new(
+                else_info,
+                "Expected `{` or `if` after `else`",
+            )),
+        };
+    }
+
+    /// Consumes tokens up to and including the `}` matching an already consumed `{`.
+    fn skip_block<I>(iter: &mut Peekable<I>, open_info: TokenInfo) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let mut depth = 1;
+        for (token, _) in iter.by_ref() {
+            if token.kind != TokenKind::Symbol {
+                continue;
+            }
+            match token.literal.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+        return Err(ParseError::new(
+            open_info,
+            "Block is missing a closing brace `}`",
+        ));
+    }
+
+    /// Reads `@name` or `@name(args...)`, arguments being literal values or constant expressions.
+    fn parse_annotation<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Annotations, token_info)?;
+        let Some((token_name, token_name_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_info,
+                "Expected annotation name after `@`",
+            ));
+        };
+
+        if token_name.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                token_name_info,
+                "Expected annotation name after `@`",
+            ));
+        }
+
+        let mut args = Vec::new();
+        let is_paren = match iter.peek() {
+            Some((next, _)) => next.kind == TokenKind::Symbol && next.literal == "(",
+            None => false,
+        };
+        if is_paren {
+            for (value, value_info) in self.parse_call_args(iter, &token_name.literal)? {
+                match value.infer_prop_value() {
+                    Ok(value) => args.push(value),
+                    Err(message) => return Err(ParseError::new(value_info, message)),
+                }
+            }
+        }
+
+        let annotation = Annotation::new(token_name.literal, args);
+        self.annotations.push((annotation, token_info));
+        return Ok(());
+    }
+
+    fn emit_prop(
+        &mut self,
+        mut prop: Prop,
+        token_info: TokenInfo,
+        token_val_info: TokenInfo,
+    ) -> Result<(), ParseError> {
+        self.prop_count += 1;
+        if self.prop_count > self.limits.max_props {
+            return Err(ParseError::limit(
+                token_val_info,
+                Limit::Props,
+                &self.limits,
+            ));
+        }
+
+        prop.annotations = self.take_annotations();
+        Self::check_unit_annotation(&prop, token_val_info)?;
+        prop.doc = self.take_doc();
+        self.events.push_back(Event::Prop {
+            name: prop.name,
+            value: prop.value,
+            unit: prop.unit,
+            annotations: prop.annotations,
+            doc: prop.doc,
+            span: Span {
+                start: token_info,
+                end: token_val_info,
+            },
+        });
+        return Ok(());
+    }
+
+    /// Constants visible at the current position, inner scopes shadowing outer ones.
+    fn visible_consts(&self) -> HashMap<String, PropValue> {
+        let mut consts = HashMap::new();
+        for scope in &self.consts {
+            consts.extend(scope.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        return consts;
+    }
+
+    /// Checks for declarations left open at the end of the input.
+    fn finish(&mut self) -> Result<(), ParseError> {
+        self.drop_docs();
+
+        if let Some((annotation, annotation_info)) = self.annotations.first() {
+            return Err(ParseError::new(
+                *annotation_info,
+                "Annotation `@".to_owned()
+                    + &annotation.name
+                    + "` must be followed by a thing or prop declaration",
+            ));
+        }
+
+        if let Some((_, open_info)) = self.conditionals.last() {
+            return Err(ParseError::new(
+                *open_info,
+                "Block is missing a closing brace `}`",
+            ));
+        }
+
+        if let Some(name) = self.open_things.pop() {
+            return Err(ParseError::new(
+                TokenInfo::new(0, 0),
+                "Token `".to_owned() + &name + "` is missing a closing brace `}`",
+            ));
+        }
+        return Ok(());
+    }
+
+    fn take_doc(&mut self) -> Option<String> {
+        if self.docs.is_empty() {
+            return None;
+        }
+        let lines: Vec<String> = std::mem::take(&mut self.docs)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect();
+        return Some(lines.join("\n"));
+    }
+
+    fn drop_docs(&mut self) {
+        let Some((_, doc_info)) = self.docs.first() else {
+            return;
+        };
+        self.diagnostics.push(Diagnostic {
+            token_info: *doc_info,
+            message: "Doc comment is not followed by a thing or prop declaration".to_string(),
+        });
+        self.docs.clear();
+    }
+
+    fn take_annotations(&mut self) -> Vec<Annotation> {
+        return std::mem::take(&mut self.annotations)
+            .into_iter()
+            .map(|(annotation, _)| annotation)
+            .collect();
+    }
+
+    fn is_block_keyword(&self, token: &Token) -> bool {
+        return token.kind == TokenKind::Word
+            && (token.literal == "thing" || self.block_keywords.contains(&token.literal));
+    }
+
+    /// Records the child names of `things` and their descendants, which were parsed before.
+    fn close_children(&mut self, prefix: &str, things: &Things) {
+        for thing in things {
+            let path = prefix.to_owned() + &thing.name;
+            let children = thing.things.iter().map(|child| child.name.clone());
+            let closed = self.closed_children.entry(path.clone()).or_default();
+            closed.extend(children);
+            self.close_children(&(path + "/"), &thing.things);
+        }
+    }
+
+    /// `unset` and `remove` are statements directly in a patch block, elsewhere they're prop names.
+    fn is_patch_statement(&self, token: &Token) -> bool {
+        return match token.literal.as_str() {
+            "patch" => self.open_things.is_empty(),
+            "unset" | "remove" => self.patching && self.open_things.len() == 1,
+            _ => false,
+        };
+    }
+
+    /// Reads `patch "Path" {`, only allowed at the top level.
+    fn parse_patch<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Patches, token_info)?;
+        let (token_path, token_path_info) = match iter.next() {
+            Some((token_path, token_path_info)) if token_path.kind == TokenKind::String => {
+                (token_path, token_path_info)
+            }
+            Some((_, next_info)) => {
+                return Err(ParseError::new(
+                    next_info,
+                    "Expected String path after keyword `patch`",
+                ));
+            }
+            None => {
+                return Err(ParseError::new(
+                    token_info,
+                    "Expected String path after keyword `patch`",
+                ));
+            }
+        };
+
+        let Some((token_open, token_open_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_path_info,
+                "Expected `{` after patch path",
+            ));
+        };
+        if token_open.kind != TokenKind::Symbol || token_open.literal != "{" {
+            return Err(ParseError::new(
+                token_open_info,
+                "Expected `{` after patch path",
+            ));
+        }
+
+        let path = strip_quotes(&token_path.literal).to_string();
+        let children = self.closed_children.get(&path).cloned();
+        self.siblings.push(children.unwrap_or_default());
+        self.open_things.push(path.clone());
+        self.consts.push(HashMap::new());
+        self.patching = true;
+        self.events.push_back(Event::StartPatch {
+            path: path,
+            span: Span {
+                start: token_info,
+                end: token_open_info,
+            },
+        });
+        return Ok(());
+    }
+
+    /// Reads `unset name`.
+    fn parse_unset<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        return match iter.next() {
+            Some((token_name, token_name_info)) if token_name.kind == TokenKind::Word => {
+                self.events.push_back(Event::Unset {
+                    name: token_name.literal,
+                    span: Span {
+                        start: token_info,
+                        end: token_name_info,
+                    },
+                });
+                Ok(())
+            }
+            Some((_, next_info)) => Err(ParseError::new(
+                next_info,
+                "Expected prop name after keyword `unset`",
+            )),
+            None => Err(ParseError::new(
+                token_info,
+                "Expected prop name after keyword `unset`",
+            )),
+        };
+    }
+
+    /// Reads `remove thing "Name"`, or the same with another block keyword.
+    fn parse_remove<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let (token_kw, token_kw_info) = match iter.next() {
+            Some((next, next_info)) if self.is_block_keyword(&next) => (next, next_info),
+            Some((_, next_info)) => {
+                return Err(ParseError::new(
+                    next_info,
+                    "Expected keyword `thing` after `remove`",
+                ));
+            }
+            None => {
+                return Err(ParseError::new(
+                    token_info,
+                    "Expected keyword `thing` after `remove`",
+                ));
+            }
+        };
+
+        return match iter.next() {
+            Some((token_name, token_name_info)) if token_name.kind == TokenKind::String => {
+                let name = strip_quotes(&token_name.literal).to_string();
+                self.siblings.last_mut().unwrap().remove(&name);
+                self.events.push_back(Event::RemoveThing {
+                    name: name,
+                    span: Span {
+                        start: token_info,
+                        end: token_name_info,
+                    },
+                });
+                Ok(())
+            }
+            Some((_, next_info)) => Err(ParseError::new(
+                next_info,
+                "Expected String name after keyword `".to_owned() + &token_kw.literal + "`",
+            )),
+            None => Err(ParseError::new(
+                token_kw_info,
+                "Expected String name after keyword `".to_owned() + &token_kw.literal + "`",
+            )),
+        };
+    }
+
+    fn parse_thing<I>(
+        &mut self,
+        (token, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+        is_abstract: bool,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let mut kind = None;
+        if token.literal != "thing" {
+            self.require(Feature::Kinds, token_info)?;
+            kind = Some(token.literal.clone());
+        }
+
+        let Some((mut token_p1, mut token_p1_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_info,
+                "Expected String name after keyword `".to_owned() + &token.literal + "`",
+            ));
+        };
+
+        if token_p1.kind == TokenKind::Symbol && token_p1.literal == ":" && kind.is_none() {
+            self.require(Feature::Kinds, token_p1_info)?;
+            let Some((token_kind, token_kind_info)) = iter.next() else {
+                return Err(ParseError::new(
+                    token_p1_info,
+                    "Expected kind name after `thing:`",
+                ));
+            };
+
+            if token_kind.kind != TokenKind::Word {
+                return Err(ParseError::new(
+                    token_kind_info,
+                    "Expected kind name after `thing:`",
+                ));
+            }
+
+            kind = Some(token_kind.literal);
+            let Some(next) = iter.next() else {
+                return Err(ParseError::new(
+                    token_kind_info,
+                    "Expected String name after keyword `thing`",
+                ));
+            };
+            (token_p1, token_p1_info) = next;
+        }
+
+        // anonymous things go straight to `extends` or `{`
+        let mut name = String::new();
+        let (mut token_p2, mut token_p2_info) = (token_p1, token_p1_info);
+        if token_p2.kind == TokenKind::String {
+            name = strip_quotes(&token_p2.literal).to_string();
+            if self.unique_names && self.siblings.last().unwrap().contains(&name) {
+                return Err(ParseError::new(
+                    token_p2_info,
+                    "Duplicate thing `".to_owned() + &name + "`",
+                ));
+            }
+
+            let Some(next) = iter.next() else {
+                return Err(ParseError::new(
+                    token_p2_info,
+                    "Expected `{` after thing name",
+                ));
+            };
+            (token_p2, token_p2_info) = next;
+        } else if token_p2.literal != "{" && token_p2.literal != "extends" {
+            return Err(ParseError::new(
+                token_p2_info,
+                "Expected String name after keyword `".to_owned() + &token.literal + "`",
+            ));
+        } else {
+            self.require(Feature::AnonymousThings, token_p2_info)?;
+        }
+
+        let mut base = None;
+
+        if token_p2.kind == TokenKind::Word && token_p2.literal == "extends" {
+            self.require(Feature::Inheritance, token_p2_info)?;
+            let Some((token_base, token_base_info)) = iter.next() else {
+                return Err(ParseError::new(
+                    token_p2_info,
+                    "Expected String base name after keyword `extends`",
+                ));
+            };
+
+            if token_base.kind != TokenKind::String {
+                return Err(ParseError::new(
+                    token_base_info,
+                    "Expected String base name after keyword `extends`",
+                ));
+            }
+
+            base = Some((
+                strip_quotes(&token_base.literal).to_string(),
+                token_base_info,
+            ));
+            let Some(next) = iter.next() else {
+                return Err(ParseError::new(
+                    token_base_info,
+                    "Expected `{` after thing name",
+                ));
+            };
+            (token_p2, token_p2_info) = next;
+        }
+
+        if token_p2.kind != TokenKind::Symbol || token_p2.literal != "{" {
+            return Err(ParseError::new(
+                token_p2_info,
+                "Expected `{` after thing name",
+            ));
+        }
+
+        if self.open_things.len() >= self.limits.max_depth {
+            return Err(ParseError::limit(token_p2_info, Limit::Depth, &self.limits));
+        }
+        self.thing_count += 1;
+        if self.thing_count > self.limits.max_things {
+            return Err(ParseError::limit(
+                token_p2_info,
+                Limit::Things,
+                &self.limits,
+            ));
+        }
+
+        if !name.is_empty() {
+            self.siblings.last_mut().unwrap().insert(name.clone());
+        }
+        self.siblings.push(HashSet::new());
+        self.open_things.push(name.clone());
+        self.consts.push(HashMap::new());
+
+        let annotations = self.take_annotations();
+        let doc = self.take_doc();
+        self.events.push_back(Event::StartThing {
+            name: name,
+            kind: kind,
+            base: base,
+            is_abstract: is_abstract,
+            annotations: annotations,
+            doc: doc,
+            span: Span {
+                start: token_info,
+                end: token_p2_info,
+            },
+        });
+        return Ok(());
+    }
+
+    fn parse_prop<I>(
+        &mut self,
+        (token, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        if self.open_things.is_empty() {
+            return Err(ParseError::new(
+                token_info,
+                "Unexpected prop definition outside of thing",
+            ));
+        }
+
+        let (prop, token_val_info) = self.read_typed_prop((token, token_info), iter)?;
+        return self.emit_prop(prop, token_info, token_val_info);
+    }
+
+    fn parse_untyped_prop<I>(
+        &mut self,
+        (token, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        // a bare word is only a prop declaration when directly followed by `=`
+        let Some((token_eq, token_eq_info)) = iter.next() else {
+            return Err(ParseError::new(token_info, "Unexpected token"));
+        };
+
+        if token_eq.kind != TokenKind::Symbol || token_eq.literal != "=" {
+            return Err(ParseError::new(token_info, "Unexpected token"));
+        }
+
+        if self.open_things.is_empty() {
+            return Err(ParseError::new(
+                token_info,
+                "Unexpected prop definition outside of thing",
+            ));
+        }
+
+        self.require(Feature::UntypedProps, token_info)?;
+        let (prop, token_val_info) =
+            self.read_untyped_value((token, token_info), token_eq_info, iter)?;
+        return self.emit_prop(prop, token_info, token_val_info);
+    }
+
+    fn parse_const<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Constants, token_info)?;
+        let Some((token, token_p1_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_info,
+                "Expected name after keyword `const`",
+            ));
+        };
+
+        if token.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                token_p1_info,
+                "Expected name after keyword `const`",
+            ));
+        }
+
+        let (prop, name_info) = if self.is_type_keyword(&token.literal) {
+            let (prop, _) = self.read_typed_prop((token, token_p1_info), iter)?;
+            (prop, token_p1_info)
+        } else {
+            let Some((token_eq, token_eq_info)) = iter.next() else {
+                return Err(ParseError::new(
+                    token_p1_info,
+                    "Expected `=` symbol after constant name",
+                ));
+            };
+
+            if token_eq.kind != TokenKind::Symbol || token_eq.literal != "=" {
+                return Err(ParseError::new(
+                    token_eq_info,
+                    "Expected `=` symbol after constant name",
+                ));
+            }
+
+            let (prop, _) = self.read_untyped_value((token, token_p1_info), token_eq_info, iter)?;
+            (prop, token_p1_info)
+        };
+
+        let scope = self.consts.last_mut().unwrap();
+        if scope.contains_key(&prop.name) {
+            return Err(ParseError::new(
+                name_info,
+                "Constant `".to_owned() + &prop.name + "` is already declared in this scope",
+            ));
+        }
+
+        scope.insert(prop.name, prop.value);
+        return Ok(());
+    }
+
+    fn is_type_keyword(&self, literal: &str) -> bool {
+        return PropType::from_keyword(literal).is_some()
+            || self.enums.contains_key(literal)
+            || self.prop_types.contains(literal);
+    }
+
+    /// Whether the declared version has `feature`, always true when the file declares none.
+    fn allows(&self, feature: Feature) -> bool {
+        return match self.version {
+            Some(version) => version >= feature.since(),
+            None => true,
+        };
+    }
+
+    /// Rejects syntax newer than the version the file declares.
+    fn require(&self, feature: Feature, token_info: TokenInfo) -> Result<(), ParseError> {
+        let Some(version) = self.version.filter(|version| *version < feature.since()) else {
+            return Ok(());
+        };
+
+        return Err(ParseError::new(
+            token_info,
+            "Feature `".to_owned()
+                + feature.name()
+                + "` requires fdl "
+                + &feature.since().to_string()
+                + ", but the file declares fdl "
+                + &version.to_string(),
+        ));
+    }
+
+    /// Reads the `fdl <version>` directive, only recognized as the very first statement.
+    fn parse_version<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let Some((token, token_p1_info)) = iter.next() else {
+            return Err(ParseError::new(token_info, "Expected version after `fdl`"));
+        };
+
+        if token.kind != TokenKind::Number {
+            return Err(ParseError::new(
+                token_p1_info,
+                "Expected version after `fdl`",
+            ));
+        }
+
+        let Some(version) = Version::from_literal(&token.literal) else {
+            return Err(ParseError::new(
+                token_p1_info,
+                "Invalid version `".to_owned() + &token.literal + "`",
+            ));
+        };
+
+        if version.major > CURRENT.major {
+            return Err(ParseError::new(
+                token_p1_info,
+                "Unsupported fdl version `".to_owned()
+                    + &version.to_string()
+                    + "`, this parser supports up to `"
+                    + &CURRENT.to_string()
+                    + "`",
+            ));
+        }
+
+        self.version = Some(version);
+        return Ok(());
+    }
+
+    fn lookup_const(&self, name: &str) -> Option<&PropValue> {
+        return self.consts.iter().rev().find_map(|scope| scope.get(name));
+    }
+
+    /// Reads `<name> = <value>` following a type keyword and parses the value as that type.
+    fn read_typed_prop<I>(
+        &self,
+        (token, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(Prop, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let ty = match PropType::from_keyword(&token.literal) {
+            Some(ty) => ty,
+            None if self.enums.contains_key(&token.literal) => PropType::Enum(token.literal),
+            None if self.prop_types.contains(&token.literal) => PropType::Custom(token.literal),
+            None => {
+                return Err(ParseError::new(
+                    token_info,
+                    "Unexpected prop type `".to_owned() + &token.literal + "`",
+                ));
+            }
+        };
+
+        if matches!(ty, PropType::Duration | PropType::Date | PropType::DateTime) {
+            self.require(Feature::TimeTypes, token_info)?;
+        }
+        match ty {
+            PropType::Long | PropType::UInt | PropType::Double => {
+                self.require(Feature::WideNumbers, token_info)?
+            }
+            PropType::Custom(_) => self.require(Feature::CustomTypes, token_info)?,
+            PropType::Bytes => self.require(Feature::Bytes, token_info)?,
+            PropType::Color => self.require(Feature::Colors, token_info)?,
+            PropType::Vec2 | PropType::Vec3 | PropType::Vec4 => {
+                self.require(Feature::Vectors, token_info)?
+            }
+            _ => {}
+        }
+
+        let Some((token_name, token_name_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_info,
+                "Expected name after prop type",
+            ));
+        };
+
+        if token_name.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                token_name_info,
+                "Expected name after prop type",
+            ));
+        }
+
+        let prop_name = token_name.literal;
+
+        let Some((token_eq, token_eq_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_name_info,
+                "Expected `=` symbol prop name",
+            ));
+        };
+
+        if token_eq.kind != TokenKind::Symbol || token_eq.literal != "=" {
+            return Err(ParseError::new(
+                token_eq_info,
+                "Expected `=` symbol prop name",
+            ));
+        }
+
+        let (value, value_info) = self.parse_expr(iter, Some(&ty), 0, token_eq_info)?;
+        let unit = value.unit();
+        let value = match (&ty, value) {
+            (PropType::Custom(name), Value::String(literal)) => self
+                .prop_types
+                .parse(name, &literal)
+                .unwrap()
+                .map_err(|message| {
+                    "Invalid `".to_owned() + name + "` value `" + &literal + "`: " + &message
+                }),
+            (ty, value) => value.to_prop_value(ty),
+        };
+        return match value {
+            Ok(value) => Ok((Self::new_prop(prop_name, value, unit), value_info)),
+            Err(message) => Err(ParseError::new(value_info, message)),
+        };
+    }
+
+    /// Reads the value following `<name> =` and infers the prop type from it.
+    fn read_untyped_value<I>(
+        &self,
+        (token, token_info): (Token, TokenInfo),
+        token_eq_info: TokenInfo,
+        iter: &mut Peekable<I>,
+    ) -> Result<(Prop, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        if self.require_type_annotations {
+            return Err(ParseError::new(
+                token_info,
+                "Missing type annotation for prop `".to_owned() + &token.literal + "`",
+            ));
+        }
+
+        let (value, value_info) = self.parse_expr(iter, None, 0, token_eq_info)?;
+        let unit = value.unit();
+        return match value.infer_prop_value() {
+            Ok(value) => Ok((Self::new_prop(token.literal, value, unit), value_info)),
+            Err(message) => Err(ParseError::new(value_info, message)),
+        };
+    }
+
+    /// `@unit("m")` with a known unit declares what the prop measures, values written with a
+    /// unit of another dimension are rejected. Other units, like `m/s`, aren't checked.
+    fn check_unit_annotation(prop: &Prop, token_val_info: TokenInfo) -> Result<(), ParseError> {
+        let expected = match prop.annotation("unit").map(|unit| unit.args.as_slice()) {
+            Some([PropValue::String(name)]) => Unit::lookup(name),
+            _ => None,
+        };
+        let unit = prop.unit.as_deref().and_then(Unit::lookup);
+        let (Some(expected), Some(unit)) = (expected, unit) else {
+            return Ok(());
+        };
+        if unit.dimension == expected.dimension {
+            return Ok(());
+        }
+
+        return Err(ParseError::new(
+            token_val_info,
+            "Unit `".to_owned()
+                + unit.name
+                + "` doesn't match `@unit(\""
+                + expected.name
+                + "\")`, expected a "
+                + expected.dimension.name(),
+        ));
+    }
+
+    fn new_prop(name: String, value: PropValue, unit: Option<&Unit>) -> Prop {
+        let mut prop = Prop::new(name, value);
+        prop.unit = unit.map(|unit| unit.name.to_string());
+        return prop;
+    }
+
+    /// Reads the unit suffix directly following a number literal, like the `ms` of `250ms`.
+    fn parse_unit<I>(
+        &self,
+        (token, token_info): (&Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<Option<&'static Unit>, ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let is_adjacent = match iter.peek() {
+            Some((next, next_info)) => {
+                next.kind == TokenKind::Word && Self::is_attached((token, token_info), *next_info)
+            }
+            None => false,
+        };
+        if !is_adjacent {
+            return Ok(None);
+        }
+
+        let (token_unit, token_unit_info) = iter.next().unwrap();
+        self.require(Feature::Units, token_unit_info)?;
+        return match Unit::lookup(&token_unit.literal) {
+            Some(unit) => Ok(Some(unit)),
+            None => Err(ParseError::new(
+                token_unit_info,
+                "Unknown unit `".to_owned() + &token_unit.literal + "`",
+            )),
+        };
+    }
+
+    fn binary_precedence(op: &str) -> Option<u8> {
+        return match op {
+            "||" => Some(1),
+            "&&" => Some(2),
+            "==" | "!=" => Some(3),
+            "<" | "<=" | ">" | ">=" => Some(4),
+            "+" | "-" => Some(5),
+            "*" | "/" | "%" => Some(6),
+            _ => None,
+        };
+    }
+
+    /// Parses and folds an expression whose binary operators bind at least as tightly as
+    /// `min_precedence`. `expected` is the declared prop type, used to resolve enum variants.
+    fn parse_expr<I>(
+        &self,
+        iter: &mut Peekable<I>,
+        expected: Option<&PropType>,
+        min_precedence: u8,
+        prev_info: TokenInfo,
+    ) -> Result<(Value, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let (mut lhs, lhs_info) = self.parse_unary(iter, expected, prev_info)?;
+
+        loop {
+            let precedence = match iter.peek() {
+                Some((token, _)) if token.kind == TokenKind::Symbol => {
+                    Self::binary_precedence(&token.literal)
+                }
+                _ => None,
+            };
+            let Some(precedence) = precedence.filter(|p| *p >= min_precedence) else {
+                break;
+            };
+
+            let (token_op, token_op_info) = iter.next().unwrap();
+            self.require(Feature::Expressions, token_op_info)?;
+            let (rhs, _) = self.parse_expr(iter, expected, precedence + 1, token_op_info)?;
+            lhs = match Value::binary(&token_op.literal, lhs, rhs) {
+                Ok(value) => value,
+                Err(message) => return Err(ParseError::new(lhs_info, message)),
+            };
+            self.check_string_len(&lhs, lhs_info)?;
+        }
+
+        return Ok((lhs, lhs_info));
+    }
+
+    fn check_string_len(&self, value: &Value, token_info: TokenInfo) -> Result<(), ParseError> {
+        return match value {
+            Value::String(val) if val.len() > self.limits.max_string_bytes => Err(
+                ParseError::limit(token_info, Limit::StringBytes, &self.limits),
+            ),
+            _ => Ok(()),
+        };
+    }
+
+    /// Every nested unary operator and parenthesized expression passes through here, so this is
+    /// where recursion in prop values is bounded.
+    fn parse_unary<I>(
+        &self,
+        iter: &mut Peekable<I>,
+        expected: Option<&PropType>,
+        prev_info: TokenInfo,
+    ) -> Result<(Value, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let depth = self.expr_depth.get() + 1;
+        if depth > self.limits.max_depth.min(MAX_VALUE_DEPTH) {
+            let limits = ParseLimits {
+                max_depth: self.limits.max_depth.min(MAX_VALUE_DEPTH),
+                ..self.limits
+            };
+            return Err(ParseError::limit(prev_info, Limit::Depth, &limits));
+        }
+
+        self.expr_depth.set(depth);
+        let result = self.parse_unary_operand(iter, expected, prev_info);
+        self.expr_depth.set(depth - 1);
+        return result;
+    }
+
+    fn parse_unary_operand<I>(
+        &self,
+        iter: &mut Peekable<I>,
+        expected: Option<&PropType>,
+        prev_info: TokenInfo,
+    ) -> Result<(Value, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let is_unary = match iter.peek() {
+            Some((token, _)) => {
+                token.kind == TokenKind::Symbol && (token.literal == "-" || token.literal == "!")
+            }
+            None => false,
+        };
+        if !is_unary {
+            return self.parse_primary(iter, expected, prev_info);
+        }
+
+        let (token_op, token_op_info) = iter.next().unwrap();
+        self.require(Feature::Expressions, token_op_info)?;
+        let (operand, _) = self.parse_unary(iter, expected, token_op_info)?;
+        return match Value::unary(&token_op.literal, operand) {
+            Ok(value) => Ok((value, token_op_info)),
+            Err(message) => Err(ParseError::new(token_op_info, message)),
+        };
+    }
+
+    fn parse_primary<I>(
+        &self,
+        iter: &mut Peekable<I>,
+        expected: Option<&PropType>,
+        prev_info: TokenInfo,
+    ) -> Result<(Value, TokenInfo), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let Some((token, token_info)) = iter.next() else {
+            return Err(ParseError::new(
+                prev_info,
+                "Expected value after prop declaration",
+            ));
+        };
+
+        let value = match token.kind {
+            TokenKind::Number => {
+                let value = if token.literal.contains('.') {
+                    Value::float_from_literal(&token.literal)
+                } else {
+                    Value::int_from_literal(&token.literal)
+                };
+                match self.parse_unit((&token, token_info), iter)? {
+                    Some(unit) => value.map(|value| value.with_unit(unit)),
+                    None => value,
+                }
+            }
+            TokenKind::String => {
+                let value = Value::String(strip_quotes(&token.literal).to_string());
+                self.check_string_len(&value, token_info)?;
+                Ok(value)
+            }
+            TokenKind::Word if Self::is_bytes_prefix((&token, token_info), iter) => {
+                Ok(self.bytes_value((&token, token_info), iter)?)
+            }
+            TokenKind::Word if Self::is_call(&token, iter) => {
+                let feature = match token.literal.as_str() {
+                    "rgb" => Feature::Colors,
+                    "flag" => Feature::Conditionals,
+                    _ => Feature::Environment,
+                };
+                self.require(feature, token_info)?;
+                let args = self.parse_call_args(iter, &token.literal)?;
+                self.call_value(&token.literal, args)
+            }
+            TokenKind::Word => self.word_value(&token.literal, expected),
+            TokenKind::Symbol if token.literal == "(" => {
+                self.require(Feature::Expressions, token_info)?;
+                let (value, _) = self.parse_expr(iter, expected, 0, token_info)?;
+                let mut components = vec![value];
+                loop {
+                    match iter.next() {
+                        Some((token_next, token_next_info)) if token_next.literal == "," => {
+                            let (value, _) = self.parse_expr(iter, None, 0, token_next_info)?;
+                            components.push(value);
+                        }
+                        Some((token_close, _)) if token_close.literal == ")" => break,
+                        Some((_, token_close_info)) => {
+                            return Err(ParseError::new(
+                                token_close_info,
+                                "Expected `)` to close `(`",
+                            ));
+                        }
+                        None => {
+                            return Err(ParseError::new(token_info, "Expected `)` to close `(`"))
+                        }
+                    }
+                }
+
+                if components.len() == 1 {
+                    Ok(components.pop().unwrap())
+                } else {
+                    self.require(Feature::Vectors, token_info)?;
+                    Self::vector_value(components)
+                }
+            }
+            TokenKind::Color => {
+                self.require(Feature::Colors, token_info)?;
+                match Color::from_hex(&token.literal) {
+                    Some(color) => Ok(Value::Prop(PropValue::Color(color))),
+                    None => Err("Invalid color `".to_owned() + &token.literal + "`"),
+                }
+            }
+            TokenKind::Symbol if token.literal == "@" => {
+                self.require(Feature::Refs, token_info)?;
+                let Some((token_path, token_path_info)) = iter.next() else {
+                    return Err(ParseError::new(
+                        token_info,
+                        "Expected String path after `@`",
+                    ));
+                };
+
+                if token_path.kind != TokenKind::String {
+                    return Err(ParseError::new(
+                        token_path_info,
+                        "Expected String path after `@`",
+                    ));
+                }
+
+                let prop = Prop::ref_from_literal("", "@".to_owned() + &token_path.literal);
+                match prop.value {
+                    PropValue::Err => Err(TYPE_MISMATCH.to_string()),
+                    value => Ok(Value::Prop(value)),
+                }
+            }
+            TokenKind::Symbol | TokenKind::DocComment | TokenKind::Error(_)
+                if expected.is_some() =>
+            {
+                Err(TYPE_MISMATCH.to_string())
+            }
+            TokenKind::Symbol | TokenKind::DocComment | TokenKind::Error(_) => {
+                Err("Unable to infer prop type from value".to_string())
+            }
+        };
+
+        return match value {
+            Ok(value) => Ok((value, token_info)),
+            Err(message) => Err(ParseError::new(token_info, message)),
+        };
+    }
+
+    /// Whether the token at `next_info` directly follows `token`, without whitespace in between.
+    fn is_attached((token, token_info): (&Token, TokenInfo), next_info: TokenInfo) -> bool {
+        return next_info.line == token_info.line
+            && next_info.col == token_info.col + token.literal.chars().count();
+    }
+
+    /// Whether the token is the `x` or `b64` prefix of a `x"deadbeef"` or `b64"3q2+7w=="` literal.
+    fn is_bytes_prefix<I>((token, token_info): (&Token, TokenInfo), iter: &mut Peekable<I>) -> bool
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        if token.literal != "x" && token.literal != "b64" {
+            return false;
+        }
+        return match iter.peek() {
+            Some((next, next_info)) => {
+                next.kind == TokenKind::String && Self::is_attached((token, token_info), *next_info)
+            }
+            None => false,
+        };
+    }
+
+    fn bytes_value<I>(
+        &self,
+        (token, token_info): (&Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<Value, ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Bytes, token_info)?;
+        let (token_data, _) = iter.next().unwrap();
+        let data = strip_quotes(&token_data.literal);
+        let bytes = match token.literal.as_str() {
+            "x" => decode_hex(data),
+            _ => decode_base64(data),
+        };
+        return match bytes {
+            Ok(bytes) => Ok(Value::Prop(PropValue::Bytes(bytes))),
+            Err(message) => Err(ParseError::new(token_info, message)),
+        };
+    }
+
+    fn is_call<I>(token: &Token, iter: &mut Peekable<I>) -> bool
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let is_paren = match iter.peek() {
+            Some((next, _)) => next.kind == TokenKind::Symbol && next.literal == "(",
+            None => false,
+        };
+        return is_paren
+            && matches!(
+                token.literal.as_str(),
+                "env" | "env_int" | "env_float" | "env_bool" | "flag" | "rgb"
+            );
+    }
+
+    /// Parses the parenthesized, comma separated arguments of a value function like `env(...)`.
+    fn parse_call_args<I>(
+        &self,
+        iter: &mut Peekable<I>,
+        name: &str,
+    ) -> Result<Vec<(Value, TokenInfo)>, ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        let (_, mut last_info) = iter.next().unwrap();
+        let mut args = Vec::new();
+        loop {
+            if let Some((token, _)) = iter.peek() {
+                if token.kind == TokenKind::Symbol && token.literal == ")" && args.is_empty() {
+                    iter.next();
+                    break;
+                }
+            }
+
+            let arg = self.parse_expr(iter, None, 0, last_info)?;
+            last_info = arg.1;
+            args.push(arg);
+
+            match iter.next() {
+                Some((token, _)) if token.kind == TokenKind::Symbol && token.literal == ")" => {
+                    break
+                }
+                Some((token, token_info))
+                    if token.kind == TokenKind::Symbol && token.literal == "," =>
+                {
+                    last_info = token_info
+                }
+                Some((_, token_info)) => {
+                    return Err(ParseError::new(
+                        token_info,
+                        "Expected `,` or `)` in arguments of `".to_owned() + name + "`",
+                    ));
+                }
+                None => {
+                    return Err(ParseError::new(
+                        last_info,
+                        "Expected `)` to close arguments of `".to_owned() + name + "`",
+                    ));
+                }
+            }
+        }
+        return Ok(args);
+    }
+
+    /// Builds a `vec2`, `vec3` or `vec4` from the components of `(x, y, ...)`.
+    fn vector_value(components: Vec<Value>) -> Result<Value, String> {
+        let mut values = Vec::new();
+        for component in &components {
+            match component {
+                Value::Int(val) => values.push(*val as f64),
+                Value::Float(val) => values.push(*val),
+                other => {
+                    return Err("Vector components must be numbers, found `".to_owned()
+                        + &other.type_name()
+                        + "`")
+                }
+            }
+        }
+
+        let value = match values.as_slice() {
+            [x, y] => PropValue::Vec2(Vec2::new(*x, *y)),
+            [x, y, z] => PropValue::Vec3(Vec3::new(*x, *y, *z)),
+            [x, y, z, w] => PropValue::Vec4(Vec4::new(*x, *y, *z, *w)),
+            _ => {
+                return Err(
+                    "Vectors have 2 to 4 components, found ".to_owned() + &values.len().to_string()
+                )
+            }
+        };
+        return Ok(Value::Prop(value));
+    }
+
+    /// Evaluates `env("NAME")` and `env("NAME", default)` and their typed `env_int`, `env_float`
+    /// and `env_bool` variants against the parser's environment.
+    fn call_value(&self, name: &str, args: Vec<(Value, TokenInfo)>) -> Result<Value, String> {
+        if name == "rgb" {
+            let channels: Option<Vec<u8>> = args
+                .iter()
+                .map(|(arg, _)| match arg {
+                    Value::Int(val) => u8::try_from(*val).ok(),
+                    _ => None,
+                })
+                .collect();
+            let color = match channels.as_deref() {
+                Some([r, g, b]) => Color::rgb(*r, *g, *b),
+                Some([r, g, b, a]) => Color::rgba(*r, *g, *b, *a),
+                _ => return Err("`rgb` expects 3 or 4 int channels between 0 and 255".to_string()),
+            };
+            return Ok(Value::Prop(PropValue::Color(color)));
+        }
+
+        if name == "flag" {
+            return match args.as_slice() {
+                [(Value::String(flag), _)] => Ok(Value::Bool(self.flags.contains(flag))),
+                _ => Err("`flag` expects a single String flag name".to_string()),
+            };
+        }
+
+        let (var, default) = match args.as_slice() {
+            [(Value::String(var), _)] => (var, None),
+            [(Value::String(var), _), (default, _)] => (var, Some(default.clone())),
+            _ => {
+                return Err("`".to_owned()
+                    + name
+                    + "` expects a String variable name and an optional default")
+            }
+        };
+
+        let (type_name, matches_type): (&str, fn(&Value) -> bool) = match name {
+            "env_int" => ("int", |v| matches!(v, Value::Int(_))),
+            "env_float" => ("float", |v| matches!(v, Value::Int(_) | Value::Float(_))),
+            "env_bool" => ("bool", |v| matches!(v, Value::Bool(_))),
+            _ => ("string", |v| matches!(v, Value::String(_))),
+        };
+
+        if let Some(default) = default.as_ref().filter(|default| !matches_type(default)) {
+            return Err("Default of `".to_owned()
+                + name
+                + "` must be of type `"
+                + type_name
+                + "`, found `"
+                + &default.type_name()
+                + "`");
+        }
+
+        let Some(literal) = self.env.var(var) else {
+            return match default {
+                Some(default) => Ok(default),
+                None => Err("Environment variable `".to_owned() + var + "` is not set"),
+            };
+        };
+
+        let value = match type_name {
+            "int" => Value::int_from_literal(literal.trim()).ok(),
+            "float" => Value::float_from_literal(literal.trim()).ok(),
+            "bool" => match literal.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => Some(Value::String(literal)),
+        };
+
+        return match value {
+            Some(value) => Ok(value),
+            None => {
+                Err("Environment variable `".to_owned() + var + "` is not a valid " + type_name)
+            }
+        };
+    }
+
+    /// Resolves a bare word: a bool literal, a variant of the expected enum type or a constant.
+    fn word_value(&self, literal: &str, expected: Option<&PropType>) -> Result<Value, String> {
+        if literal == "true" || literal == "false" {
+            return Ok(Value::Bool(literal == "true"));
+        }
+
+        let def = match expected {
+            Some(PropType::Enum(name)) => self.enums.get(name),
+            _ => None,
+        };
+
+        if let Some(def) = def.filter(|def| def.has_variant(literal)) {
+            let prop = Prop::enum_from_literal("", def, literal);
+            return Ok(Value::Prop(prop.value));
+        }
+
+        if let Some(value) = self.lookup_const(literal) {
+            return Ok(Value::from_prop_value(value));
+        }
+
+        return match def {
+            Some(def) => Err("Unknown variant `".to_owned()
+                + literal
+                + "` for enum `"
+                + &def.name
+                + "`, expected one of: "
+                + &def.variants.join(", ")),
+            None => Err("Undefined constant `".to_owned() + literal + "`"),
+        };
+    }
+
+    fn parse_enum<I>(
+        &mut self,
+        (_, token_info): (Token, TokenInfo),
+        iter: &mut Peekable<I>,
+    ) -> Result<(), ParseError>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        self.require(Feature::Enums, token_info)?;
+        let Some((token_name, token_name_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_info,
+                "Expected name after keyword `enum`",
+            ));
+        };
+
+        if token_name.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                token_name_info,
+                "Expected name after keyword `enum`",
+            ));
+        }
+
+        if self.enums.contains_key(&token_name.literal) {
+            return Err(ParseError::new(
+                token_name_info,
+                "Enum `".to_owned() + &token_name.literal + "` is already declared",
+            ));
+        }
+
+        let Some((token_open, token_open_info)) = iter.next() else {
+            return Err(ParseError::new(
+                token_name_info,
+                "Expected `{` after enum name",
+            ));
+        };
+
+        if token_open.kind != TokenKind::Symbol || token_open.literal != "{" {
+            return Err(ParseError::new(
+                token_open_info,
+                "Expected `{` after enum name",
+            ));
+        }
+
+        let mut def = EnumDef::new(token_name.literal);
+        let mut last_info = token_open_info;
+        loop {
+            let Some((token, token_info)) = iter.next() else {
+                return Err(ParseError::new(
+                    last_info,
+                    "Enum `".to_owned() + &def.name + "` is missing a closing brace `}`",
+                ));
+            };
+
+            match token.kind {
+                TokenKind::Symbol if token.literal == "}" => break,
+                TokenKind::Symbol if token.literal == "," && !def.variants.is_empty() => {}
+                TokenKind::Word if !def.has_variant(&token.literal) => {
+                    def.variants.push(token.literal)
+                }
+                TokenKind::Word => {
+                    return Err(ParseError::new(
+                        token_info,
+                        "Duplicate variant `".to_owned() + &token.literal + "`",
+                    ));
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        token_info,
+                        "Expected variant name in enum `".to_owned() + &def.name + "`",
+                    ));
+                }
+            }
+            last_info = token_info;
+        }
+
+        if def.variants.is_empty() {
+            return Err(ParseError::new(
+                token_name_info,
+                "Enum `".to_owned() + &def.name + "` must declare at least one variant",
+            ));
+        }
+
+        self.enums.insert(def.name.clone(), def);
+        return Ok(());
+    }
+}
+
+impl<I: Iterator<Item = (Token, TokenInfo)>> PullParser<I> {
+    /// Reads events with the default options, use [`ParserBuilder::events`] to configure them.
+    pub fn new(tokens: I) -> Self {
+        return Parser::build().events(tokens);
+    }
+
+    fn with_reader(reader: Reader, tokens: I) -> Self {
+        let stream_error = Rc::new(RefCell::new(None));
+        let tokens = CheckedTokens {
+            tokens: tokens,
+            num_tokens: 0,
+            limits: reader.limits,
+            error: stream_error.clone(),
+        };
+
+        return Self {
+            reader: reader,
+            tokens: tokens.peekable(),
+            stream_error: stream_error,
+            started: false,
+            done: false,
+        };
+    }
+
+    /// Version from the leading `fdl` directive, known once the first event was read.
+    pub fn version(&self) -> Option<Version> {
+        return self.reader.version;
+    }
+
+    /// Enums declared so far.
+    pub fn enums(&self) -> &HashMap<String, EnumDef> {
+        return &self.reader.enums;
+    }
+
+    /// Problems found so far that didn't stop parsing.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        return &self.reader.diagnostics;
+    }
+
+    fn step(&mut self) -> Result<(), ParseError> {
+        if !self.started {
+            self.started = true;
+            if let Some((token, _)) = self.tokens.peek() {
+                if token.kind == TokenKind::Word && token.literal == "fdl" {
+                    let item = self.tokens.next().unwrap();
+                    return self.reader.parse_version(item, &mut self.tokens);
+                }
+            }
+        }
+
+        return match self.tokens.next() {
+            Some(item) => self.reader.parse_token(item, &mut self.tokens),
+            None => {
+                self.done = true;
+                self.reader.finish()
+            }
+        };
+    }
+}
+
+impl<I: Iterator<Item = (Token, TokenInfo)>> Iterator for PullParser<I> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.reader.events.pop_front() {
+                return Some(event);
+            }
+            if self.done {
+                return None;
+            }
+
+            let result = self.step();
+            let stream_error = self.stream_error.borrow_mut().take();
+            if let Some(err) = stream_error.or(result.err()) {
+                self.done = true;
+                self.reader.events.push_back(Event::Error(err));
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = (Token, TokenInfo)>> Iterator for CheckedTokens<I> {
+    type Item = (Token, TokenInfo);
+
+    fn next(&mut self) -> Option<(Token, TokenInfo)> {
+        if self.error.borrow().is_some() {
+            return None;
+        }
+
+        let (token, token_info) = self.tokens.next()?;
+        self.num_tokens += 1;
+        let error = match token.kind {
+            _ if self.num_tokens > self.limits.max_tokens => {
+                ParseError::limit(token_info, Limit::Tokens, &self.limits)
+            }
+            TokenKind::Error(LexError::Limit(limit)) => {
+                ParseError::limit(token_info, limit, &self.limits)
+            }
+            TokenKind::Error(LexError::UnterminatedString) => {
+                ParseError::new(token_info, token.literal)
+            }
+            _ => return Some((token, token_info)),
+        };
+        *self.error.borrow_mut() = Some(error);
+        return None;
+    }
+}
+
+impl ParserBuilder {
+    /// Rejects props declared without a leading type keyword, e.g. `x = 10`.
+    pub fn require_type_annotations(mut self, require: bool) -> ParserBuilder {
+        self.reader.require_type_annotations = require;
+        return self;
+    }
+
+    /// Replaces the default [`ParseLimits`]. String literals are also bounded by the lexer, see
+    /// [`crate::lexer::Lexer::with_limits`].
+    pub fn limits(mut self, limits: ParseLimits) -> ParserBuilder {
+        self.reader.limits = limits;
+        return self;
+    }
+
+    /// Sets a flag that `flag("name")` in `if` conditions and prop values evaluates to `true` for.
+    pub fn flag(mut self, name: impl Into<String>) -> ParserBuilder {
+        self.reader.flags.insert(name.into());
+        return self;
+    }
+
+    /// Rejects things with the same name as an earlier sibling, instead of keeping both in order.
+    pub fn unique_names(mut self, value: bool) -> ParserBuilder {
+        self.reader.unique_names = value;
+        return self;
+    }
+
+    /// Accepts `<keyword> "Name" { ... }` blocks as things of kind `keyword`, e.g. `enemy`.
+    pub fn block_keyword(mut self, keyword: impl Into<String>) -> ParserBuilder {
+        self.reader.block_keywords.insert(keyword.into());
+        return self;
+    }
+
+    /// Registers a prop type, e.g. `uuid id = "..."`. Values are written as strings and turned
+    /// into a `PropValue::Custom` by `parse`, whose error message is reported at the value.
+    /// Built-in types and enums with the same name take precedence.
+    pub fn prop_type(
+        mut self,
+        name: impl Into<String>,
+        parse: impl Fn(&str) -> Result<Vec<PropValue>, String> + 'static,
+    ) -> ParserBuilder {
+        self.reader.prop_types.register(name, parse);
+        return self;
+    }
+
+    /// Replaces the process environment read by `env(...)` values, e.g. with a `HashMap` in tests.
+    pub fn environment(mut self, env: impl Environment + 'static) -> ParserBuilder {
+        self.reader.env = Rc::new(env);
+        return self;
+    }
+
+    /// Starts the tree from things parsed earlier, e.g. from another file. `patch` blocks, refs
+    /// and `extends` can reach them, and they come first in the parsed things.
+    pub fn things(mut self, things: Things) -> ParserBuilder {
+        self.things = things;
+        return self;
+    }
+
+    /// Continues after an `earlier` parse, e.g. of another file: its things start the tree as
+    /// with [`ParserBuilder::things`], and its enums, constants and templates are in scope.
+    /// Constants can be declared again, hiding the earlier ones.
+    pub(crate) fn after(mut self, earlier: Parser) -> ParserBuilder {
+        self.things = earlier.things;
+        self.templates = earlier.templates;
+        self.reader.enums = earlier.enums;
+        self.reader.consts.insert(0, earlier.constants);
+        return self;
+    }
+
+    /// Leaves `${...}` placeholders for [`Parser::interpolate_strings`], e.g. to substitute them
+    /// once the things are merged with others.
+    pub(crate) fn defer_interpolation(mut self) -> ParserBuilder {
+        self.interpolate = false;
+        return self;
+    }
+
+    /// Reads the tokens as a stream of events instead of building a tree. Things set with
+    /// [`ParserBuilder::things`] aren't part of the stream.
+    pub fn events<I>(self, tokens: I) -> PullParser<I>
+    where
+        I: Iterator<Item = (Token, TokenInfo)>,
+    {
+        return PullParser::with_reader(self.reader, tokens);
+    }
+
+    pub fn parse(
+        self,
+        tokens: impl Iterator<Item = (Token, TokenInfo)>,
+    ) -> Result<Parser, ParseError> {
+        let mut reader = self.reader;
+        if reader.unique_names {
+            reader.close_children("", &self.things);
+        }
+        let mut parser = Parser::new();
+        parser.things = self.things;
+        parser.templates = self.templates;
+        parser.interpolate = self.interpolate;
+        let events = PullParser::with_reader(reader, tokens);
+        return Parser::from_events_over(parser, events);
+    }
+}
+
+impl Default for ParserBuilder {
+    fn default() -> Self {
+        return Parser::build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core::PropValue, lexer::Lexer};
+
+    fn populate_parser(source: &str) -> Result<Parser, ParseError> {
+        let lexer = Lexer::new(source);
+        return Parser::from_tokens(lexer);
+    }
+
+    #[test]
+    fn parses_a_thing() {
+        let parser = populate_parser(r#"thing "MyThing" {} "#).unwrap();
+        assert_eq!(parser.things.len(), 1);
+        assert_eq!(parser.things.get("MyThing").unwrap().name, "MyThing");
+    }
+
+    #[test]
+    fn parses_sibbling_things() {
+        let parser = populate_parser(
+            r#"
+            thing "MyThing" {}
+            thing "MyThing2" {}
+        "#,
+        )
+        .unwrap();
+        assert_eq!(parser.things.len(), 2);
+    }
+
+    #[test]
+    fn parses_nested_things() {
+        let parser = populate_parser(
+            r#"
+            thing "MyThing" {
+                thing "Nested" {}
+            } 
+        "#,
+        )
+        .unwrap();
+        assert_eq!(parser.things.len(), 1);
+        assert_eq!(parser.things.get("MyThing").unwrap().num_things(), 1);
+    }
+
+    #[test]
+    fn thing_as_last_token_leads_to_error() {
+        assert!(populate_parser(r#"thing"#).is_err());
+    }
+
+    #[test]
+    fn thing_not_followed_by_string_leads_to_error() {
+        let err = populate_parser(r#"thing 12"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 6));
+    }
+
+    #[test]
+    fn thing_and_name_without_opening_brace_leads_to_error() {
+        let err = populate_parser(r#"thing "Name" a"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 13));
+    }
+
+    #[test]
+    fn thing_without_closing_brace_leads_to_error() {
+        let err = populate_parser(r#"thing "Name" {"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 0));
+    }
+
+    #[test]
+    fn parses_int_prop() {
+        let parser = populate_parser(r#" thing "Name" { int prop = 12 } "#).unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let prop = thing.props.get("prop").unwrap();
+        assert_eq!(prop.value, PropValue::Int(12))
+    }
+
+    #[test]
+    fn parses_float_prop() {
+        let parser = populate_parser(r#" thing "Name" { float prop = 12.1 } "#).unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let prop = thing.props.get("prop").unwrap();
+        assert_eq!(prop.value, PropValue::Float(12.1))
+    }
+
+    #[test]
+    fn parses_bool_prop() {
+        let parser = populate_parser(r#" thing "Name" { bool prop = true } "#).unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let prop = thing.props.get("prop").unwrap();
+        assert_eq!(prop.value, PropValue::Bool(true))
+    }
+
+    #[test]
+    fn parses_string_prop() {
+        let parser = populate_parser(r#" thing "Name" { string prop = "Hello" } "#).unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let prop = thing.props.get("prop").unwrap();
+        assert_eq!(prop.value, PropValue::String("Hello".to_string()));
+    }
+
+    #[test]
+    fn top_level_prop_definition_results_in_error() {
+        let err = populate_parser(r#"string prop = "Hello""#).unwrap_err();
+        assert_eq!(err.message, "Unexpected prop definition outside of thing");
+    }
+
+    #[test]
+    fn unsuported_prop_type_results_in_error() {
+        let err = populate_parser(r#" thing "Name" { bloop prop = 12 } "#).unwrap_err();
+        assert_eq!(err.message, "Unexpected token");
+        assert_eq!(err.token_info, TokenInfo::new(0, 16));
+    }
+
+    #[test]
+    fn missing_prop_name_results_in_error() {
+        let err = populate_parser(r#"thing "Name" { int = 12 }"#).unwrap_err();
+        assert_eq!(err.message, "Expected name after prop type");
+        let err = populate_parser(r#"thing "Name" { int"#).unwrap_err();
+        assert_eq!(err.message, "Expected name after prop type");
+    }
+
+    #[test]
+    fn missing_prop_eq_results_in_error() {
+        let err = populate_parser(r#"thing "Name" { int prop 12 }"#).unwrap_err();
+        assert_eq!(err.message, "Expected `=` symbol prop name");
+        let err = populate_parser(r#"thing "Name" { int prop"#).unwrap_err();
+        assert_eq!(err.message, "Expected `=` symbol prop name");
+    }
+
+    #[test]
+    fn missing_prop_value_results_in_error() {
+        let err = populate_parser(r#"thing "Name" { int prop = }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+        let err = populate_parser(r#"thing "Name" { int prop ="#).unwrap_err();
+        assert_eq!(err.message, "Expected value after prop declaration");
+    }
+
+    #[test]
+    fn prop_value_not_matching_type_results_in_error() {
+        let err = populate_parser(r#"thing "Name" { int prop = true }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+    }
+
+    #[test]
+    fn infers_prop_types_from_literals() {
+        let parser =
+            populate_parser(r#"thing "Name" { x = 10.2 name = "Bob" on = true count = 3 }"#)
+                .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        assert_eq!(thing.props.get("x").unwrap().value, PropValue::Float(10.2));
+        assert_eq!(
+            thing.props.get("name").unwrap().value,
+            PropValue::String("Bob".to_string())
+        );
+        assert_eq!(thing.props.get("on").unwrap().value, PropValue::Bool(true));
+        assert_eq!(thing.props.get("count").unwrap().value, PropValue::Int(3));
+    }
+
+    #[test]
+    fn untyped_prop_with_uninferrable_value_results_in_error() {
+        let err = populate_parser(r#"thing "Name" { x = }"#).unwrap_err();
+        assert_eq!(err.message, "Unable to infer prop type from value");
+        assert_eq!(err.token_info, TokenInfo::new(0, 19));
+    }
+
+    #[test]
+    fn untyped_top_level_prop_results_in_error() {
+        let err = populate_parser(r#"x = 12"#).unwrap_err();
+        assert_eq!(err.message, "Unexpected prop definition outside of thing");
+    }
+
+    #[test]
+    fn required_type_annotations_reject_untyped_props() {
+        let source = r#"thing "Name" { int typed = 1 untyped = 2 }"#;
+        let err = Parser::build()
+            .require_type_annotations(true)
+            .parse(Lexer::new(source))
+            .unwrap_err();
+        assert_eq!(err.message, "Missing type annotation for prop `untyped`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 29));
+    }
+
+    #[test]
+    fn parses_64_bit_and_unsigned_props() {
+        let parser = populate_parser(
+            r#"
+            thing "Name" {
+                long l = -9000000000
+                i64 l2 = 1
+                uint u = 18446744073709551615
+                u64 u2 = 2
+                double d = 9000.01
+                f64 d2 = -0.5
+            }
+        "#,
+        )
+        .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let value = |name: &str| &thing.props.get(name).unwrap().value;
+        assert_eq!(*value("l"), PropValue::Long(-9000000000));
+        assert_eq!(*value("l2"), PropValue::Long(1));
+        assert_eq!(*value("u"), PropValue::UInt(u64::MAX));
+        assert_eq!(*value("u2"), PropValue::UInt(2));
+        assert_eq!(*value("d"), PropValue::Double(9000.01));
+        assert_eq!(*value("d2"), PropValue::Double(-0.5));
+    }
+
+    #[test]
+    fn out_of_range_prop_values_result_in_error() {
+        let err = populate_parser(r#"thing "Name" { int i = 3000000000 }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Value `3000000000` is out of range for prop type `int`"
+        );
+        let err = populate_parser(r#"thing "Name" { uint u = -1 }"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 24));
+    }
+
+    #[test]
+    fn untyped_large_integers_widen_to_64_bits() {
+        let parser =
+            populate_parser(r#"thing "Name" { a = -3000000000 b = 10000000000000000000 c = -1 }"#)
+                .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        assert_eq!(
+            thing.props.get("a").unwrap().value,
+            PropValue::Long(-3000000000)
+        );
+        assert_eq!(
+            thing.props.get("b").unwrap().value,
+            PropValue::UInt(10000000000000000000)
+        );
+        assert_eq!(thing.props.get("c").unwrap().value, PropValue::Int(-1));
+    }
+
+    #[test]
+    fn parses_enum_declarations_and_props() {
+        let parser = populate_parser(
+            r#"
+            enum LogLevel { Debug, Info, Warn, }
+            thing "Name" {
+                LogLevel level = Info
+            }
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            parser.enums.get("LogLevel").unwrap().variants,
+            vec!["Debug", "Info", "Warn"]
+        );
+        let thing = parser.things.get("Name").unwrap();
+        assert_eq!(
+            thing.props.get("level").unwrap().value,
+            PropValue::Enum {
+                ty: "LogLevel".to_string(),
+                variant: "Info".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_enum_variant_results_in_error_listing_variants() {
+        let err = populate_parser(
+            r#"enum LogLevel { Debug, Info, Warn } thing "Name" { LogLevel level = Inof }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unknown variant `Inof` for enum `LogLevel`, expected one of: Debug, Info, Warn"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 68));
+    }
+
+    #[test]
+    fn malformed_enum_declarations_result_in_error() {
+        let err = populate_parser(r#"enum E { A, A }"#).unwrap_err();
+        assert_eq!(err.message, "Duplicate variant `A`");
+        let err = populate_parser(r#"enum E { }"#).unwrap_err();
+        assert_eq!(err.message, "Enum `E` must declare at least one variant");
+        let err = populate_parser(r#"enum E { A } enum E { B }"#).unwrap_err();
+        assert_eq!(err.message, "Enum `E` is already declared");
+        let err = populate_parser(r#"enum E { A"#).unwrap_err();
+        assert_eq!(err.message, "Enum `E` is missing a closing brace `}`");
+    }
+
+    #[test]
+    fn parses_and_resolves_refs() {
+        let parser = populate_parser(
+            r#"
+            thing "Loot" { thing "Common" { int gold = 5 } }
+            thing "Goblin" {
+                ref loot = @"Loot/Common"
+                fallback = @"Loot"
+            }
+        "#,
+        )
+        .unwrap();
+        let goblin = parser.things.get("Goblin").unwrap();
+        let loot = &goblin.props.get("loot").unwrap().value;
+        assert_eq!(*loot, PropValue::Ref("Loot/Common".to_string()));
+        assert_eq!(parser.resolve_ref(loot).unwrap().name, "Common");
+        let fallback = &goblin.props.get("fallback").unwrap().value;
+        assert_eq!(parser.resolve_ref(fallback).unwrap().name, "Loot");
+        assert!(parser.resolve_path("Loot/Rare").is_none());
+    }
+
+    #[test]
+    fn dangling_ref_results_in_error() {
+        let err = populate_parser(r#"thing "A" { ref r = @"A/B" }"#).unwrap_err();
+        assert_eq!(err.message, "Unresolved reference `A/B`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 20));
+    }
+
+    #[test]
+    fn refs_to_parents_siblings_and_self_are_not_cycles() {
+        let source = r#"
+            thing "A" {
+                ref me = @"A"
+                thing "B" { ref up = @"A" }
+                thing "C" { ref prev = @"A/B" ref root = @"D" }
+            }
+            thing "D" { ref child = @"A/B" }
+        "#;
+        let parser = populate_parser(source).unwrap();
+        let c = parser.resolve_path("A/C").unwrap();
+        assert_eq!(
+            parser.resolve_ref(&c.props["prev"].value).unwrap().name,
+            "B"
+        );
+    }
+
+    #[test]
+    fn cyclic_refs_result_in_error() {
+        let err = populate_parser(
+            r#"
+            thing "A" { ref b = @"B" }
+            thing "B" { ref a = @"A" }
+        "#,
+        )
+        .unwrap_err();
+        assert_eq!(err.message, "Reference `A` forms a cycle");
+        assert_eq!(err.token_info, TokenInfo::new(2, 32));
+    }
+
+    #[test]
+    fn ref_must_be_a_path_string() {
+        let err = populate_parser(r#"thing "A" { ref r = "A" }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+        let err = populate_parser(r#"thing "A" { ref r = @12 }"#).unwrap_err();
+        assert_eq!(err.message, "Expected String path after `@`");
+    }
+
+    #[test]
+    fn constants_can_be_used_as_prop_values() {
+        let parser = populate_parser(
+            r#"
+            const int TILE = 32
+            const NAME = "Bob"
+            enum LogLevel { Debug, Info }
+            const LogLevel LEVEL = Debug
+            thing "Name" {
+                int width = TILE
+                double scale = TILE
+                name = NAME
+                LogLevel level = LEVEL
+            }
+        "#,
+        )
+        .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        assert_eq!(thing.props.get("width").unwrap().value, PropValue::Int(32));
+        assert_eq!(
+            thing.props.get("scale").unwrap().value,
+            PropValue::Double(32.0)
+        );
+        assert_eq!(
+            thing.props.get("name").unwrap().value,
+            PropValue::String("Bob".to_string())
+        );
+        assert_eq!(thing.props.get("level").unwrap().value.to_string(), "Debug");
+    }
+
+    #[test]
+    fn constants_are_lexically_scoped_and_can_be_shadowed() {
+        let parser = populate_parser(
+            r#"
+            const int TILE = 32
+            thing "Outer" {
+                const int TILE = 16
+                thing "Inner" { int width = TILE }
+                int width = TILE
+            }
+            thing "Other" { int width = TILE }
+        "#,
+        )
+        .unwrap();
+        let outer = parser.things.get("Outer").unwrap();
+        let inner = outer.get_thing("Inner").unwrap();
+        let other = parser.things.get("Other").unwrap();
+        assert_eq!(inner.props.get("width").unwrap().value, PropValue::Int(16));
+        assert_eq!(outer.props.get("width").unwrap().value, PropValue::Int(16));
+        assert_eq!(other.props.get("width").unwrap().value, PropValue::Int(32));
+
+        let err =
+            populate_parser(r#"thing "A" { const X = 1 } thing "B" { int x = X }"#).unwrap_err();
+        assert_eq!(err.message, "Undefined constant `X`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 46));
+    }
+
+    #[test]
+    fn constants_are_type_checked_against_prop_type() {
+        let err = populate_parser(r#"const HALF = 0.5 thing "A" { int x = HALF }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 37));
+        let err = populate_parser(r#"const int X = 1 const X = 2"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Constant `X` is already declared in this scope"
+        );
+        let err = populate_parser(r#"const int X = "one""#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+    }
+
+    #[test]
+    fn folds_arithmetic_expressions_into_declared_type() {
+        let parser = populate_parser(
+            r#"
+            const int TILE = 32
+            thing "Name" {
+                int width = TILE * 4 + 2
+                float half = 1.0 / 3
+                int grouped = (1 + 2) * -3
+                long big = 3000000000 * 2
+                double precise = 0.1 + 0.2
+                inferred = 7 / 2
+            }
+        "#,
+        )
+        .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let value = |name: &str| &thing.props.get(name).unwrap().value;
+        assert_eq!(*value("width"), PropValue::Int(130));
+        assert_eq!(*value("half"), PropValue::Float(1.0 / 3.0));
+        assert_eq!(*value("grouped"), PropValue::Int(-9));
+        assert_eq!(*value("big"), PropValue::Long(6000000000));
+        assert_eq!(*value("precise"), PropValue::Double(0.1 + 0.2));
+        assert_eq!(*value("inferred"), PropValue::Int(3));
+    }
+
+    #[test]
+    fn folds_comparison_boolean_and_string_expressions() {
+        let parser = populate_parser(
+            r#"
+            const NAME = "Bob"
+            enum LogLevel { Debug, Info }
+            const LogLevel LEVEL = Info
+            thing "Name" {
+                bool big = 3 * 2 >= 6 && !(1 == 2)
+                bool either = false || 1.5 < 2
+                bool named = NAME != "Alice"
+                string greeting = "Hello " + NAME + "!"
+                LogLevel level = LEVEL
+            }
+        "#,
+        )
+        .unwrap();
+        let thing = parser.things.get("Name").unwrap();
+        let value = |name: &str| &thing.props.get(name).unwrap().value;
+        assert_eq!(*value("big"), PropValue::Bool(true));
+        assert_eq!(*value("either"), PropValue::Bool(true));
+        assert_eq!(*value("named"), PropValue::Bool(true));
+        assert_eq!(
+            *value("greeting"),
+            PropValue::String("Hello Bob!".to_string())
+        );
+        assert_eq!(value("level").to_string(), "Info");
+    }
+
+    #[test]
+    fn expression_errors_are_reported_at_the_expression() {
+        let err = populate_parser(r#"thing "A" { int x = 1 + (4 / 0) }"#).unwrap_err();
+        assert_eq!(err.message, "Division by zero");
+        assert_eq!(err.token_info, TokenInfo::new(0, 25));
+
+        let err = populate_parser(r#"thing "A" { int x = 2 * "two" }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Operator `*` cannot be applied to `int` and `string`"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 20));
+
+        let err = populate_parser(r#"thing "A" { int x = 1.5 * 2 }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unable to parse prop value matching declared prop type"
+        );
+
+        let err = populate_parser(r#"thing "A" { int x = (1 + 2 }"#).unwrap_err();
+        assert_eq!(err.message, "Expected `)` to close `(`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 27));
+    }
+
+    #[test]
+    fn interpolates_props_from_thing_ancestors_and_constants() {
+        let parser = populate_parser(
+            r#"
+            const EXT = "png"
+            thing "Assets" {
+                root = "/data"
+                thing "Hero" {
+                    string icon = "${dir}/${name}.${EXT}"
+                    string dir = "${root}/assets"
+                    name = "hero"
+                    price = "$${price}"
+                }
+            }
+        "#,
+        )
+        .unwrap();
+        let hero = parser
+            .things
+            .get("Assets")
+            .unwrap()
+            .get_thing("Hero")
+            .unwrap();
+        assert_eq!(
+            hero.props.get("icon").unwrap().value,
+            PropValue::String("/data/assets/hero.png".to_string())
+        );
+        assert_eq!(
+            hero.props.get("price").unwrap().value,
+            PropValue::String("${price}".to_string())
+        );
+    }
+
+    #[test]
+    fn interpolation_of_unknown_name_results_in_error() {
+        let err = populate_parser(r#"thing "A" { string s = "${nope}" }"#).unwrap_err();
+        assert_eq!(err.message, "Unknown name `nope` in string interpolation");
+        assert_eq!(err.token_info, TokenInfo::new(0, 23));
+    }
+
+    #[test]
+    fn cyclic_interpolation_results_in_error() {
+        let err = populate_parser(r#"thing "A" { a = "${b}" b = "${a}" }"#).unwrap_err();
+        assert!(err.message.ends_with("forms a cycle"));
+        let err = populate_parser(r#"thing "A" { a = "${a}" }"#).unwrap_err();
+        assert_eq!(err.message, "String interpolation of `a` forms a cycle");
+    }
+
+    fn parse_with_env(source: &str, vars: &[(&str, &str)]) -> Result<Parser, ParseError> {
+        let env: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        return Parser::build().environment(env).parse(Lexer::new(source));
+    }
+
+    #[test]
+    fn env_values_are_read_from_the_environment_provider() {
+        let parser = parse_with_env(
+            r#"
+            thing "Server" {
+                string host = env("HOST", "localhost")
+                int port = env_int("PORT", 80) + 1
+                float ratio = env_float("RATIO", 0.5)
+                bool debug = env_bool("DEBUG", false)
+                string user = env("USER")
+            }
+        "#,
+            &[("PORT", "8080"), ("DEBUG", "true"), ("USER", "bob")],
+        )
+        .unwrap();
+        let server = parser.things.get("Server").unwrap();
+        let value = |name: &str| &server.props.get(name).unwrap().value;
+        assert_eq!(*value("host"), PropValue::String("localhost".to_string()));
+        assert_eq!(*value("port"), PropValue::Int(8081));
+        assert_eq!(*value("ratio"), PropValue::Float(0.5));
+        assert_eq!(*value("debug"), PropValue::Bool(true));
+        assert_eq!(*value("user"), PropValue::String("bob".to_string()));
+    }
+
+    #[test]
+    fn invalid_env_values_result_in_error() {
+        let err = parse_with_env(r#"thing "A" { string s = env("MISSING") }"#, &[]).unwrap_err();
+        assert_eq!(err.message, "Environment variable `MISSING` is not set");
+        assert_eq!(err.token_info, TokenInfo::new(0, 23));
+
+        let source = r#"thing "A" { int port = env_int("PORT", 80) }"#;
+        let err = parse_with_env(source, &[("PORT", "eighty")]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Environment variable `PORT` is not a valid int"
+        );
+
+        let err =
+            parse_with_env(r#"thing "A" { int p = env_int("PORT", "80") }"#, &[]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Default of `env_int` must be of type `int`, found `string`"
+        );
+
+        let err = parse_with_env(r#"thing "A" { string s = env(HOST) }"#, &[]).unwrap_err();
+        assert_eq!(err.message, "Undefined constant `HOST`");
+    }
+
+    #[test]
+    fn env_is_only_a_function_when_called() {
+        let parser = parse_with_env(r#"const env = 3 thing "A" { int x = env }"#, &[]).unwrap();
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(thing.props.get("x").unwrap().value, PropValue::Int(3));
+    }
+
+    #[test]
+    fn things_inherit_props_and_children_from_their_base() {
+        let parser = populate_parser(
+            r#"
+            thing "Orc" extends "Goblin" {
+                int hp = 30
+                thing "Weapon" { int damage = 5 }
+            }
+            thing "Goblin" {
+                int hp = 10
+                string name = "${kind} grunt"
+                kind = "goblin"
+                thing "Weapon" { string type = "club" int damage = 2 }
+                thing "Loot" { int gold = 1 }
+            }
+        "#,
+        )
+        .unwrap();
+        let orc = parser.things.get("Orc").unwrap();
+        assert_eq!(orc.name, "Orc");
+        assert_eq!(orc.props.get("hp").unwrap().value, PropValue::Int(30));
+        assert_eq!(
+            orc.props.get("name").unwrap().value,
+            PropValue::String("goblin grunt".to_string())
+        );
+        let weapon = orc.get_thing("Weapon").unwrap();
+        assert_eq!(weapon.props.get("damage").unwrap().value, PropValue::Int(5));
+        assert_eq!(
+            weapon.props.get("type").unwrap().value,
+            PropValue::String("club".to_string())
+        );
+        assert!(orc.get_thing("Loot").is_some());
+
+        let goblin = parser.things.get("Goblin").unwrap();
+        assert_eq!(goblin.props.get("hp").unwrap().value, PropValue::Int(10));
+    }
+
+    #[test]
+    fn abstract_things_are_only_templates() {
+        let parser = populate_parser(
+            r#"
+            abstract thing "Enemy" { int hp = 10 }
+            thing "Enemies" {
+                abstract thing "Flying" extends "Enemy" { bool flies = true }
+                thing "Bat" extends "Enemies/Flying" { }
+            }
+        "#,
+        )
+        .unwrap();
+        assert!(!parser.things.contains("Enemy"));
+        assert!(parser.templates.contains_key("Enemy"));
+        assert!(parser.templates.contains_key("Enemies/Flying"));
+
+        let enemies = parser.things.get("Enemies").unwrap();
+        assert_eq!(enemies.num_things(), 1);
+        let bat = enemies.get_thing("Bat").unwrap();
+        assert_eq!(bat.props.get("hp").unwrap().value, PropValue::Int(10));
+        assert_eq!(bat.props.get("flies").unwrap().value, PropValue::Bool(true));
+    }
+
+    #[test]
+    fn invalid_inheritance_results_in_error() {
+        let err = populate_parser(r#"thing "Orc" extends "Goblin" {}"#).unwrap_err();
+        assert_eq!(err.message, "Unknown base thing `Goblin`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 20));
+
+        let err = populate_parser(
+            r#"
+            thing "A" extends "B" {}
+            thing "B" extends "A" {}
+        "#,
+        )
+        .unwrap_err();
+        assert!(err.message.ends_with("forms a cycle"));
+
+        let err = populate_parser(r#"thing "A" { thing "B" extends "A" {} }"#).unwrap_err();
+        assert_eq!(err.message, "Inheritance of `A` forms a cycle");
+
+        let err = populate_parser(r#"abstract "A" {}"#).unwrap_err();
+        assert_eq!(err.message, "Expected keyword `thing` after `abstract`");
+    }
+
+    #[test]
+    fn registered_block_keywords_declare_things_of_that_kind() {
+        let source = r#"
+            enemy "Goblin" { int hp = 10 }
+            item "Sword" { int damage = 3 }
+            thing:enemy "Bat" { }
+            thing "Level" { enemy "Boss" extends "Goblin" { } thing "Door" { } }
+        "#;
+        let parser = Parser::build()
+            .block_keyword("enemy")
+            .block_keyword("item")
+            .parse(Lexer::new(source))
+            .unwrap();
+        assert!(parser.things.get("Goblin").unwrap().is_kind("enemy"));
+        assert!(parser.things.get("Sword").unwrap().is_kind("item"));
+        assert_eq!(parser.things.get("Level").unwrap().kind, None);
+
+        let mut enemies: Vec<&str> = parser
+            .things_of_kind("enemy")
+            .iter()
+            .map(|thing| thing.name.as_str())
+            .collect();
+        enemies.sort();
+        assert_eq!(enemies, vec!["Bat", "Boss", "Goblin"]);
+    }
+
+    #[test]
+    fn kind_annotation_sets_the_kind_of_things() {
+        let parser =
+            populate_parser(r#"thing:Enemy "Goblin" { } abstract thing:Item "Base" { }"#).unwrap();
+        assert!(parser.things.get("Goblin").unwrap().is_kind("Enemy"));
+        assert!(parser.templates.get("Base").unwrap().is_kind("Item"));
+    }
+
+    #[test]
+    fn unregistered_block_keywords_are_rejected() {
+        let err = populate_parser(r#"enemy "Goblin" { }"#).unwrap_err();
+        assert_eq!(err.message, "Unexpected token");
+        assert_eq!(err.token_info, TokenInfo::new(0, 0));
+
+        let err = populate_parser(r#"thing: "Goblin" { }"#).unwrap_err();
+        assert_eq!(err.message, "Expected kind name after `thing:`");
+    }
+
+    #[test]
+    fn same_named_things_are_kept_in_order() {
+        let source = r#"
+            thing "Path" {
+                thing "Point" { int x = 1 string label = "p${x}" }
+                thing "Point" { int x = 2 string label = "p${x}" }
+            }
+        "#;
+        let parser = populate_parser(source).unwrap();
+        let path = parser.things.get("Path").unwrap();
+        let labels: Vec<String> = path
+            .get_things("Point")
+            .iter()
+            .map(|point| point.props.get("label").unwrap().value.to_string())
+            .collect();
+        assert_eq!(labels, vec!["p1", "p2"]);
+        assert_eq!(
+            parser
+                .resolve_path("Path/Point")
+                .unwrap()
+                .props
+                .get("x")
+                .unwrap()
+                .value,
+            PropValue::Int(1)
+        );
+    }
+
+    #[test]
+    fn anonymous_things_can_repeat() {
+        let source = r#"
+            abstract waypoint "Slow" { float speed = 0.5 }
+            thing "Route" {
+                waypoint { int x = 1 }
+                waypoint extends "Slow" { int x = 2 string tag = "${x}" }
+                thing { }
+            }
+        "#;
+        let parser = Parser::build()
+            .block_keyword("waypoint")
+            .parse(Lexer::new(source))
+            .unwrap();
+        let route = parser.things.get("Route").unwrap();
+        assert_eq!(route.num_things(), 3);
+
+        let waypoints: Vec<&Thing> = route
+            .things()
+            .iter()
+            .filter(|t| t.is_kind("waypoint"))
+            .collect();
+        assert_eq!(waypoints.len(), 2);
+        assert!(waypoints.iter().all(|t| t.name.is_empty()));
+        assert_eq!(
+            waypoints[1].props.get("speed").unwrap().value,
+            PropValue::Float(0.5)
+        );
+        assert_eq!(
+            waypoints[1].props.get("tag").unwrap().value,
+            PropValue::String("2".to_string())
+        );
+    }
+
+    #[test]
+    fn inheritance_pairs_same_named_children_in_order() {
+        let source = r#"
+            thing "Base" { thing "Slot" { int a = 1 } thing "Slot" { int a = 2 } }
+            thing "Derived" extends "Base" { thing "Slot" { int b = 3 } }
+        "#;
+        let parser = populate_parser(source).unwrap();
+        let slots = parser.things.get("Derived").unwrap().get_things("Slot");
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].props.get("a").unwrap().value, PropValue::Int(1));
+        assert_eq!(slots[0].props.get("b").unwrap().value, PropValue::Int(3));
+        assert_eq!(slots[1].props.get("a").unwrap().value, PropValue::Int(2));
+    }
+
+    #[test]
+    fn unique_names_rejects_duplicate_siblings() {
+        let source = r#"thing "A" { } thing "B" { thing "A" { } } thing "A" { }"#;
+        let err = Parser::build()
+            .unique_names(true)
+            .parse(Lexer::new(source))
+            .unwrap_err();
+        assert_eq!(err.message, "Duplicate thing `A`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 48));
+    }
+
+    #[test]
+    fn unique_names_covers_things_added_by_patches() {
+        let parse = |source: &str| {
+            return Parser::build().unique_names(true).parse(Lexer::new(source));
+        };
+
+        let source = r#"thing "A" { thing "x" { } } patch "A" { thing "x" { } }"#;
+        let err = parse(source).unwrap_err();
+        assert_eq!(err.message, "Duplicate thing `x`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 46));
+
+        let source = r#"thing "A" { thing "x" { } } patch "A" { remove thing "x" thing "x" { } }"#;
+        assert!(parse(source).is_ok());
+
+        let earlier = parse(r#"thing "A" { thing "x" { } }"#).unwrap().things;
+        let err = Parser::build()
+            .unique_names(true)
+            .things(earlier)
+            .parse(Lexer::new(r#"patch "A" { thing "x" { } }"#))
+            .unwrap_err();
+        assert_eq!(err.message, "Duplicate thing `x`");
+    }
+
+    #[test]
+    fn annotations_attach_to_the_next_thing_or_prop() {
+        let source = r#"
+            const MAX = 100
+            @deprecated("use Player") @secret
+            thing "User" {
+                @unit("m/s") @range(0, MAX * 2) float speed = 3.5
+                @secret password = "hunter2"
+                int plain = 1
+            }
+        "#;
+        let parser = populate_parser(source).unwrap();
+        let user = parser.things.get("User").unwrap();
+        assert_eq!(user.annotations.len(), 2);
+        assert_eq!(
+            user.annotation("deprecated").unwrap().args,
+            vec![PropValue::String("use Player".to_string())]
+        );
+        assert!(user.annotation("secret").unwrap().args.is_empty());
+
+        let speed = user.props.get("speed").unwrap();
+        assert_eq!(
+            speed.annotation("range").unwrap().args,
+            vec![PropValue::Int(0), PropValue::Int(200)]
+        );
+        assert_eq!(
+            speed.annotation("unit").unwrap().to_string(),
+            r#"@unit("m/s")"#
+        );
+        assert!(user
+            .props
+            .get("password")
+            .unwrap()
+            .annotation("secret")
+            .is_some());
+        assert!(user.props.get("plain").unwrap().annotations.is_empty());
+    }
+
+    #[test]
+    fn dangling_annotations_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { @secret }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Annotation `@secret` must be followed by a thing or prop declaration"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 12));
+
+        let err = populate_parser(r#"@secret"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 0));
+
+        let err = populate_parser(r#"@"Path" thing "A" { }"#).unwrap_err();
+        assert_eq!(err.message, "Expected annotation name after `@`");
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_thing_or_prop() {
+        let source = r#"
+            // not documentation
+            /// The player character.
+            /// Controlled by the user.
+            @secret
+            thing "Player" {
+                /// Meters per second.
+                float speed = 3.5 // trailing remark
+                int hp = 10
+                /// Dropped, nothing is declared after it.
+            }
+            /// Dropped as well.
+            enum Mode { A }
+        "#;
+        let parser = populate_parser(source).unwrap();
+        let player = parser.things.get("Player").unwrap();
+        assert_eq!(
+            player.doc.as_deref(),
+            Some("The player character.\nControlled by the user.")
+        );
+        assert!(player.annotation("secret").is_some());
+        assert_eq!(
+            player.props.get("speed").unwrap().doc.as_deref(),
+            Some("Meters per second.")
+        );
+        assert_eq!(player.props.get("hp").unwrap().doc, None);
+    }
+
+    #[test]
+    fn conditional_blocks_follow_flags() {
+        let source = r#"
+            thing "Config" {
+                if flag("debug") {
+                    int log_level = 3
+                    thing "Profiler" { }
+                } else if flag("linux") && !flag("debug") {
+                    int log_level = 2
+                } else {
+                    int log_level = 1
+                }
+                bool debug = flag("debug")
+            }
+            if !flag("debug") { thing "Release" { } }
+        "#;
+
+        let debug = Parser::build()
+            .flag("debug")
+            .flag("linux")
+            .parse(Lexer::new(source))
+            .unwrap();
+        let config = debug.things.get("Config").unwrap();
+        assert_eq!(
+            config.props.get("log_level").unwrap().value,
+            PropValue::Int(3)
+        );
+        assert_eq!(
+            config.props.get("debug").unwrap().value,
+            PropValue::Bool(true)
+        );
+        assert!(config.get_thing("Profiler").is_some());
+        assert!(!debug.things.contains("Release"));
+
+        let linux = Parser::build()
+            .flag("linux")
+            .parse(Lexer::new(source))
+            .unwrap();
+        let config = linux.things.get("Config").unwrap();
+        assert_eq!(
+            config.props.get("log_level").unwrap().value,
+            PropValue::Int(2)
+        );
+        assert!(config.get_thing("Profiler").is_none());
+        assert!(linux.things.contains("Release"));
+
+        let other = populate_parser(source).unwrap();
+        let config = other.things.get("Config").unwrap();
+        assert_eq!(
+            config.props.get("log_level").unwrap().value,
+            PropValue::Int(1)
+        );
+    }
 
     #[test]
-    fn unsuported_prop_type_results_in_error() {
-        let err = populate_parser(r#" thing "Name" { bloop prop = 12 } "#).unwrap_err();
-        assert_eq!(err.message, "Unexpected token");
+    fn conditions_must_be_bools() {
+        let err = populate_parser(r#"thing "A" { if 1 + 2 { } }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Condition of `if` must be a `bool`, found `int`"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 15));
+    }
+
+    #[test]
+    fn unclosed_conditional_blocks_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { if false { int x = 1"#).unwrap_err();
+        assert_eq!(err.message, "Block is missing a closing brace `}`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 21));
+
+        let err = populate_parser(r#"if true { thing "A" { }"#).unwrap_err();
+        assert_eq!(err.message, "Block is missing a closing brace `}`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 8));
+    }
+
+    #[test]
+    fn version_directive_is_recorded() {
+        let parser = populate_parser("fdl 1.1 thing \"A\" { }").unwrap();
+        assert_eq!(parser.version, Some(Version::new(1, 1)));
+        assert_eq!(populate_parser("thing \"A\" { }").unwrap().version, None);
+    }
+
+    #[test]
+    fn newer_major_versions_are_rejected() {
+        let err = populate_parser("fdl 2.0").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unsupported fdl version `2.0`, this parser supports up to `1.3`"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 4));
+        assert!(populate_parser("fdl 1.9").is_ok());
+
+        let err = populate_parser("fdl abc").unwrap_err();
+        assert_eq!(err.message, "Expected version after `fdl`");
+    }
+
+    #[test]
+    fn features_are_gated_by_declared_version() {
+        let err = populate_parser("fdl 1.0\nenum Mode { A }").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `enums` requires fdl 1.1, but the file declares fdl 1.0"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(1, 0));
+
+        let source = r#"thing "A" { } thing "B" extends "A" { }"#;
+        let err = populate_parser(&("fdl 1.1 ".to_owned() + source)).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `inheritance` requires fdl 1.2, but the file declares fdl 1.1"
+        );
+        assert!(populate_parser(&("fdl 1.2 ".to_owned() + source)).is_ok());
+        assert!(populate_parser(source).is_ok());
+    }
+
+    #[test]
+    fn all_newer_syntax_is_gated_by_declared_version() {
+        let cases = [
+            ("fdl 1.0 thing \"A\" { x = 1 }", "untyped props", "1.1"),
+            (
+                "fdl 1.0 thing \"A\" { long x = 1 }",
+                "long, uint and double types",
+                "1.1",
+            ),
+            (
+                "fdl 1.0 thing \"A\" { int x = 1 + 2 }",
+                "expressions",
+                "1.1",
+            ),
+            ("fdl 1.0 thing \"A\" { int x = -1 }", "expressions", "1.1"),
+            ("fdl 1.0 thing \"A\" { int x = (1) }", "expressions", "1.1"),
+            (
+                "fdl 1.0 thing \"A\" { string x = env(\"HOME\", \"\") }",
+                "environment variables",
+                "1.1",
+            ),
+            ("fdl 1.1 thing: enemy \"A\" { }", "thing kinds", "1.2"),
+            (
+                "fdl 1.1 thing \"A\" { thing { } }",
+                "anonymous things",
+                "1.2",
+            ),
+            ("fdl 1.1\n/// Docs\nthing \"A\" { }", "doc comments", "1.2"),
+            (
+                "fdl 1.1 thing \"A\" { bool x = flag(\"debug\") }",
+                "conditional blocks",
+                "1.2",
+            ),
+        ];
+        for (source, feature, since) in cases {
+            let err = populate_parser(source).unwrap_err();
+            let declared = &source[4..7];
+            assert_eq!(
+                err.message,
+                "Feature `".to_owned()
+                    + feature
+                    + "` requires fdl "
+                    + since
+                    + ", but the file declares fdl "
+                    + declared,
+                "{}",
+                source
+            );
+        }
+
+        let err = Parser::build()
+            .block_keyword("enemy")
+            .parse(Lexer::new("fdl 1.1 enemy \"A\" { }"))
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `thing kinds` requires fdl 1.2, but the file declares fdl 1.1"
+        );
+    }
+
+    #[test]
+    fn older_versions_keep_their_meaning() {
+        let parser = populate_parser(r#"fdl 1.0 thing "A" { string s = "price ${x}" }"#).unwrap();
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(
+            thing.props.get("s").unwrap().value,
+            PropValue::String("price ${x}".to_string())
+        );
+
+        let parser =
+            populate_parser(r#"fdl 1.1 thing "A" { int x = 1 string s = "${x}" }"#).unwrap();
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(
+            thing.props.get("s").unwrap().value,
+            PropValue::String("1".to_string())
+        );
+    }
+
+    fn parse_with_limits(source: &str, limits: ParseLimits) -> Result<Parser, ParseError> {
+        let lexer = Lexer::with_limits(source, &limits);
+        return Parser::build().limits(limits).parse(lexer);
+    }
+
+    #[test]
+    fn nesting_deeper_than_the_limit_leads_to_error() {
+        let limits = ParseLimits {
+            max_depth: 2,
+            ..ParseLimits::default()
+        };
+        assert!(parse_with_limits(r#"thing "A" { thing "B" { } }"#, limits).is_ok());
+
+        let err =
+            parse_with_limits(r#"thing "A" { thing "B" { thing "C" { } } }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Depth));
+        assert_eq!(err.message, "Nesting depth exceeds the limit of 2");
+        assert_eq!(err.token_info, TokenInfo::new(0, 34));
+
+        let err = parse_with_limits(r#"thing "A" { int x = -(-(-1)) }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Depth));
+    }
+
+    #[test]
+    fn deeply_nested_values_do_not_overflow_the_stack() {
+        let source = "thing \"A\" { int x = ".to_owned() + &"(".repeat(100_000) + "1 }";
+        let err = populate_parser(&source).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Depth));
+
+        for nesting in ["(", "-", "!"] {
+            let source = "thing \"A\" { int x = ".to_owned() + &nesting.repeat(100_000) + "1 }";
+            let err = parse_with_limits(&source, ParseLimits::unlimited()).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Depth));
+            assert_eq!(err.message, "Nesting depth exceeds the limit of 128");
+        }
+    }
+
+    #[test]
+    fn token_and_count_limits_lead_to_errors() {
+        let limits = ParseLimits {
+            max_tokens: 8,
+            ..ParseLimits::default()
+        };
+        let err = parse_with_limits(r#"thing "A" { int x = 1 int y = 2 }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Tokens));
+        assert_eq!(err.token_info, TokenInfo::new(0, 26));
+
+        let limits = ParseLimits {
+            max_things: 3,
+            max_props: 3,
+            ..ParseLimits::default()
+        };
+        let err = parse_with_limits(
+            r#"thing "A" { } thing "B" { } thing "C" { } thing "D" { }"#,
+            limits,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Things));
+
+        let source = r#"thing "A" { int x = 1 int y = 2 } thing "B" extends "A" { }"#;
+        let err = parse_with_limits(source, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::Props));
+        assert_eq!(err.token_info, TokenInfo::new(0, 52));
+    }
+
+    #[test]
+    fn string_limits_cover_literals_and_built_strings() {
+        let limits = ParseLimits {
+            max_string_bytes: 4,
+            ..ParseLimits::default()
+        };
+        let err = parse_with_limits(r#"thing "A" { s = "12345" }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::StringBytes));
         assert_eq!(err.token_info, TokenInfo::new(0, 16));
+
+        let err = parse_with_limits(r#"thing "A" { s = "abc" + "de" }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::StringBytes));
+
+        let err =
+            parse_with_limits(r#"thing "A" { a = "abc" s = "${a}${a}" }"#, limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::LimitExceeded(Limit::StringBytes));
     }
 
     #[test]
-    fn missing_prop_name_results_in_error() {
-        let err = populate_parser(r#"thing "Name" { int = 12 }"#).unwrap_err();
-        assert_eq!(err.message, "Expected name after prop type");
-        let err = populate_parser(r#"thing "Name" { int"#).unwrap_err();
-        assert_eq!(err.message, "Expected name after prop type");
+    fn unterminated_strings_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { s = "oops }"#).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::Syntax);
+        assert_eq!(err.message, "Unterminated string literal");
+        assert_eq!(err.token_info, TokenInfo::new(0, 16));
     }
 
     #[test]
-    fn missing_prop_eq_results_in_error() {
-        let err = populate_parser(r#"thing "Name" { int prop 12 }"#).unwrap_err();
-        assert_eq!(err.message, "Expected `=` symbol prop name");
-        let err = populate_parser(r#"thing "Name" { int prop"#).unwrap_err();
-        assert_eq!(err.message, "Expected `=` symbol prop name");
+    fn events_follow_declaration_order() {
+        let source = "/// doc\nthing \"A\" extends \"B\" {\n  @min(0) x = 1\n  thing { }\n}";
+        let events: Vec<Event> = PullParser::new(Lexer::new(source)).collect();
+        assert_eq!(events.len(), 5);
+
+        let Event::StartThing {
+            name,
+            base,
+            doc,
+            span,
+            ..
+        } = &events[0]
+        else {
+            panic!("expected StartThing, got {:?}", events[0]);
+        };
+        assert_eq!(name, "A");
+        assert_eq!(base.as_ref().unwrap().0, "B");
+        assert_eq!(doc.as_deref(), Some("doc"));
+        assert_eq!(span.start, TokenInfo::new(1, 0));
+        assert_eq!(span.end, TokenInfo::new(1, 22));
+
+        let Event::Prop {
+            name,
+            value,
+            annotations,
+            span,
+            ..
+        } = &events[1]
+        else {
+            panic!("expected Prop, got {:?}", events[1]);
+        };
+        assert_eq!(name, "x");
+        assert_eq!(*value, PropValue::Int(1));
+        assert_eq!(annotations[0].name, "min");
+        assert_eq!(span.start, TokenInfo::new(2, 10));
+        assert_eq!(span.end, TokenInfo::new(2, 14));
+
+        assert!(matches!(&events[2], Event::StartThing { name, .. } if name.is_empty()));
+        assert!(matches!(events[3], Event::EndThing));
+        assert!(matches!(events[4], Event::EndThing));
     }
 
     #[test]
-    fn missing_prop_value_results_in_error() {
-        let err = populate_parser(r#"thing "Name" { int prop = }"#).unwrap_err();
+    fn events_can_be_read_lazily() {
+        let source = r#"thing "A" { x = 1 } thing "B" { y = 2 } }"#;
+        let mut events = PullParser::new(Lexer::new(source));
+        let found = events.find_map(|event| match event {
+            Event::Prop { name, value, .. } if name == "x" => Some(value),
+            _ => None,
+        });
+        assert_eq!(found, Some(PropValue::Int(1)));
+    }
+
+    #[test]
+    fn error_event_ends_the_stream() {
+        let source = r#"thing "A" { x = 1 } }"#;
+        let events: Vec<Event> = PullParser::new(Lexer::new(source)).collect();
+        assert_eq!(events.len(), 4);
+        let Event::Error(err) = &events[3] else {
+            panic!("expected Error, got {:?}", events[3]);
+        };
+        assert_eq!(err.message, "Unexpected symbol: `}`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 20));
+    }
+
+    #[test]
+    fn events_report_props_as_declared() {
+        let source = r#"fdl 1.2 const n = "x" thing "A" { s = "${n}" ref r = @"B" }"#;
+        let mut events = Parser::build().events(Lexer::new(source));
+        let props: Vec<PropValue> = events
+            .by_ref()
+            .filter_map(|event| match event {
+                Event::Prop { value, .. } => Some(value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(props[0], PropValue::String("${n}".to_string()));
+        assert_eq!(props[1], PropValue::Ref("B".to_string()));
+        assert_eq!(events.version(), Some(Version::new(1, 2)));
+    }
+
+    fn parse_triple(literal: &str) -> Result<Vec<PropValue>, String> {
+        let fields: Vec<&str> = literal.split(',').collect();
+        if fields.len() != 3 {
+            return Err("expected 3 components".to_string());
+        }
+        return fields
+            .iter()
+            .map(|field| match field.trim().parse::<f64>() {
+                Ok(val) => Ok(PropValue::Double(val)),
+                Err(_) => Err("`".to_owned() + field.trim() + "` is not a number"),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn custom_prop_types_parse_their_literals() {
+        let source = r#"const triple origin = "0,0,0" thing "A" { triple pos = "1," + "2,3" triple o = origin }"#;
+        let parser = Parser::build()
+            .prop_type("triple", parse_triple)
+            .parse(Lexer::new(source))
+            .unwrap();
+
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(
+            thing.props["pos"].value,
+            PropValue::Custom {
+                ty: "triple".to_string(),
+                fields: vec![
+                    PropValue::Double(1.0),
+                    PropValue::Double(2.0),
+                    PropValue::Double(3.0)
+                ],
+            }
+        );
+        assert_eq!(thing.props["pos"].value.to_string(), "triple(1, 2, 3)");
+        assert_eq!(
+            thing.props["o"].value.prop_type(),
+            Some(PropType::Custom("triple".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_custom_literals_lead_to_error() {
+        let parse = |source| {
+            return Parser::build()
+                .prop_type("triple", parse_triple)
+                .parse(Lexer::new(source))
+                .unwrap_err();
+        };
+
+        let err = parse(r#"thing "A" { triple pos = "1,x,3" }"#);
         assert_eq!(
             err.message,
-            "Unable to parse prop value matching declared prop type"
+            "Invalid `triple` value `1,x,3`: `x` is not a number"
         );
-        let err = populate_parser(r#"thing "Name" { int prop ="#).unwrap_err();
-        assert_eq!(err.message, "Expected value after prop declaration");
+        assert_eq!(err.token_info, TokenInfo::new(0, 25));
+
+        let err = parse(r#"thing "A" { triple pos = 12 }"#);
+        assert_eq!(err.message, TYPE_MISMATCH);
     }
 
     #[test]
-    fn prop_value_not_matching_type_results_in_error() {
-        let err = populate_parser(r#"thing "Name" { int prop = true }"#).unwrap_err();
+    fn unregistered_custom_types_are_rejected() {
+        let err = populate_parser(r#"thing "A" { triple pos = "1,2,3" }"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 12));
+        assert_eq!(err.message, "Unexpected token");
+    }
+
+    #[test]
+    fn unit_literals_convert_to_canonical_units() {
+        let source = r#"thing "A" {
+            float dist = 10.5m
+            cooldown = 250ms
+            int buffer = 2KiB
+            double turn = 90deg + 90deg
+            reach = 1m + 50cm
+            angle = 45deg
+            far = 0.3km
+            float narrow = 45deg
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+
+        assert_eq!(thing.props["dist"].value, PropValue::Float(10.5));
+        assert_eq!(thing.props["dist"].unit.as_deref(), Some("m"));
+        assert_eq!(thing.props["cooldown"].value, PropValue::Double(0.25));
+        assert_eq!(thing.props["cooldown"].value_in_unit(), "250ms");
+        assert_eq!(thing.props["angle"].value_in_unit(), "45deg");
+        assert_eq!(thing.props["far"].value, PropValue::Double(300.0));
+        assert_eq!(thing.props["far"].value_in_unit(), "0.3km");
+        assert_eq!(thing.props["narrow"].value_in_unit(), "45deg");
+        assert_eq!(thing.props["buffer"].value, PropValue::Int(2048));
+        assert_eq!(
+            thing.props["turn"].value,
+            PropValue::Double(std::f64::consts::PI)
+        );
+        assert_eq!(thing.props["reach"].value, PropValue::Double(1.5));
+        assert_eq!(thing.props["reach"].unit.as_deref(), Some("m"));
+    }
+
+    #[test]
+    fn units_must_be_known_and_attached() {
+        let err = populate_parser(r#"thing "A" { x = 10parsecs }"#).unwrap_err();
+        assert_eq!(err.message, "Unknown unit `parsecs`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 18));
+
+        let err = populate_parser(r#"thing "A" { x = 10 m }"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 19));
+    }
+
+    #[test]
+    fn units_of_different_dimensions_dont_mix() {
+        let err = populate_parser(r#"thing "A" { x = 1m + 1s }"#).unwrap_err();
         assert_eq!(
             err.message,
-            "Unable to parse prop value matching declared prop type"
+            "Operator `+` cannot be applied to `length` and `time`"
+        );
+
+        let err = populate_parser(r#"thing "A" { x = 1m + 1 }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Operator `+` cannot be applied to `length` and `int`"
+        );
+
+        let err = populate_parser(r#"thing "A" { int x = 250ms }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Value `250ms` is `0.25s`, which is not a whole number as prop type `int` requires"
+        );
+    }
+
+    #[test]
+    fn unit_annotations_check_the_dimension() {
+        let source = r#"thing "A" {
+            @unit("m") float dist = 25cm
+            @unit("m") float plain = 3
+            @unit("m/s") float speed = 3.5
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(thing.props["dist"].value, PropValue::Float(0.25));
+
+        let err = populate_parser(r#"thing "A" { @unit("m") float dist = 250ms }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unit `ms` doesn't match `@unit(\"m\")`, expected a length"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 36));
+    }
+
+    #[test]
+    fn units_require_fdl_1_3() {
+        let err = populate_parser(r#"fdl 1.2 thing "A" { x = 1m }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `units` requires fdl 1.3, but the file declares fdl 1.2"
+        );
+    }
+
+    #[test]
+    fn time_types_parse_from_strings() {
+        let source = r#"thing "A" {
+            duration cooldown = "1h30m"
+            duration timeout = 250ms
+            date release = "2026-10-17"
+            datetime start = "2026-10-17T14:00:00+02:00"
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+
+        assert_eq!(
+            thing.props["cooldown"].value,
+            PropValue::Duration(std::time::Duration::from_secs(5400))
+        );
+        assert_eq!(thing.props["cooldown"].value.to_string(), "PT1H30M");
+        assert_eq!(
+            thing.props["timeout"].value,
+            PropValue::Duration(std::time::Duration::from_millis(250))
+        );
+        assert_eq!(thing.props["release"].value.to_string(), "2026-10-17");
+        assert_eq!(
+            thing.props["start"].value.to_string(),
+            "2026-10-17T12:00:00Z"
+        );
+    }
+
+    #[test]
+    fn invalid_time_values_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { date d = "2026-02-30" }"#).unwrap_err();
+        assert_eq!(err.message, "Invalid `date` value `2026-02-30`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 21));
+
+        let err = populate_parser(r#"thing "A" { duration d = 10m }"#).unwrap_err();
+        assert_eq!(err.message, TYPE_MISMATCH);
+
+        let err = populate_parser(r#"fdl 1.2 thing "A" { date d = "2026-10-17" }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `duration, date and datetime types` requires fdl 1.3, but the file declares fdl 1.2"
+        );
+    }
+
+    #[test]
+    fn bytes_literals_decode_hex_and_base64() {
+        let source = r#"thing "A" { bytes hash = x"DEADbeef" key = b64"3q2+7w==" x = 1 }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+
+        let bytes = PropValue::Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(thing.props["hash"].value, bytes);
+        assert_eq!(thing.props["key"].value, bytes);
+        assert_eq!(thing.props["key"].value.to_string(), r#"x"deadbeef""#);
+        assert_eq!(thing.props["x"].value, PropValue::Int(1));
+    }
+
+    #[test]
+    fn invalid_bytes_literals_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { bytes b = x"abc" }"#).unwrap_err();
+        assert_eq!(err.message, "Hex literal has an odd number of digits");
+        assert_eq!(err.token_info, TokenInfo::new(0, 22));
+
+        let err = populate_parser(r#"thing "A" { bytes b = "abc" }"#).unwrap_err();
+        assert_eq!(err.message, TYPE_MISMATCH);
+
+        let err = populate_parser(r#"thing "A" { bytes b = x "ab" }"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 22));
+    }
+
+    #[test]
+    fn color_literals_parse_from_hex_and_rgb() {
+        let source = r#"thing "A" {
+            color tint = #ff8800
+            shade = #FF880080
+            color fill = rgb(0, 128, 255)
+            color glass = rgb(0, 128, 255, 64)
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+
+        assert_eq!(thing.get_color("tint"), Some(Color::rgb(255, 136, 0)));
+        assert_eq!(
+            thing.get_color("shade"),
+            Some(Color::rgba(255, 136, 0, 128))
+        );
+        assert_eq!(thing.get_color("fill"), Some(Color::rgb(0, 128, 255)));
+        assert_eq!(thing.get_color("glass").unwrap().a, 64);
+        assert_eq!(thing.props["shade"].value.to_string(), "#ff880080");
+    }
+
+    #[test]
+    fn invalid_colors_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { color c = #ff88 }"#).unwrap_err();
+        assert_eq!(err.message, "Invalid color `#ff88`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 22));
+
+        let err = populate_parser(r#"thing "A" { color c = rgb(256, 0, 0) }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "`rgb` expects 3 or 4 int channels between 0 and 255"
+        );
+    }
+
+    #[test]
+    fn vector_literals_pick_their_size() {
+        let source = r#"thing "A" {
+            vec2 uv = (0.5, 1)
+            pos = (1.0, 2.0, 3.0)
+            vec4 quat = (0, 0, 0, 1)
+            float scale = (2)
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let thing = parser.things.get("A").unwrap();
+
+        assert_eq!(thing.get_vec2("uv"), Some(Vec2::new(0.5, 1.0)));
+        assert_eq!(thing.get_vec3("pos"), Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(thing.get_vec4("quat").unwrap().w, 1.0);
+        assert_eq!(thing.get_vec3("uv"), None);
+        assert_eq!(thing.props["pos"].value.to_string(), "(1, 2, 3)");
+        assert_eq!(thing.props["scale"].value, PropValue::Float(2.0));
+    }
+
+    #[test]
+    fn invalid_vectors_lead_to_error() {
+        let err = populate_parser(r#"thing "A" { vec3 v = (1, 2) }"#).unwrap_err();
+        assert_eq!(err.message, TYPE_MISMATCH);
+
+        let err = populate_parser(r#"thing "A" { v = (1, "2") }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Vector components must be numbers, found `string`"
+        );
+        assert_eq!(err.token_info, TokenInfo::new(0, 16));
+
+        let err = populate_parser(r#"thing "A" { v = (1, 2, 3, 4, 5) }"#).unwrap_err();
+        assert_eq!(err.message, "Vectors have 2 to 4 components, found 5");
+    }
+
+    #[test]
+    fn patches_change_earlier_things() {
+        let source = r#"
+        const name = "world"
+        thing "Hello" {
+            thing "World" {
+                int i1 = 1
+                int i2 = 2
+                thing "Inner" { ref missing = @"Nowhere" }
+                thing "After" { greeting = "hello ${name}" }
+            }
+        }
+        patch "Hello/World" {
+            int i1 = 5
+            i3 = "new"
+            unset i2
+            remove thing "Inner"
+            thing "Added" { ref target = @"Hello/World/After" }
+        }"#;
+        let parser = populate_parser(source).unwrap();
+        let world = parser.resolve_path("Hello/World").unwrap();
+
+        assert_eq!(world.props["i1"].value, PropValue::Int(5));
+        assert_eq!(
+            world.props["i3"].value,
+            PropValue::String("new".to_string())
+        );
+        assert!(!world.props.contains_key("i2"));
+        assert!(world.get_thing("Inner").is_none());
+        assert_eq!(
+            world.get_thing("After").unwrap().props["greeting"].value,
+            PropValue::String("hello world".to_string())
+        );
+        assert_eq!(world.things.at(1).unwrap().name, "Added");
+        assert_eq!(parser.things.len(), 1);
+    }
+
+    #[test]
+    fn patch_statements_are_only_keywords_in_patch_blocks() {
+        let parser = populate_parser(r#"thing "A" { patch = 1 unset = 2 remove = 3 }"#).unwrap();
+        let thing = parser.things.get("A").unwrap();
+        assert_eq!(thing.props.len(), 3);
+
+        let err =
+            populate_parser(r#"thing "A" { } patch "A" { thing "B" { unset x } }"#).unwrap_err();
+        assert_eq!(err.token_info, TokenInfo::new(0, 38));
+    }
+
+    #[test]
+    fn missing_patch_targets_lead_to_error() {
+        let source = r#"thing "A" { x = 1 thing "B" { } }"#;
+
+        let err = populate_parser(&(source.to_owned() + r#" patch "A/C" { }"#)).unwrap_err();
+        assert_eq!(err.message, "Unknown patch target `A/C`");
+        assert_eq!(err.token_info, TokenInfo::new(0, 34));
+
+        let err = populate_parser(&(source.to_owned() + r#" patch "A" { unset y }"#)).unwrap_err();
+        assert_eq!(err.message, "Prop `y` doesn't exist in `A`");
+
+        let source = source.to_owned() + r#" patch "A/B" { remove thing "C" }"#;
+        let err = populate_parser(&source).unwrap_err();
+        assert_eq!(err.message, "Thing `C` doesn't exist in `A/B`");
+
+        let err = populate_parser(r#"fdl 1.2 thing "A" { } patch "A" { }"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Feature `patches` requires fdl 1.3, but the file declares fdl 1.2"
+        );
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow_the_stack() {
+        let count = 20_000;
+        let mut source = "thing \"A\" {\n".to_string();
+        for i in 0..count {
+            source += &format!("p{} = \"${{p{}}}\"\n", i, i + 1);
+        }
+        source += &format!("p{} = \"end\"\n}}\n", count);
+        let parser = populate_parser(&source).unwrap();
+        assert_eq!(
+            parser.things.get("A").unwrap().props["p0"].value,
+            PropValue::String("end".to_string())
+        );
+
+        let count = 9_000;
+        let mut source = String::new();
+        for i in 0..count {
+            source += &format!(
+                "thing \"T{}\" extends \"T{}\" {{ ref r = @\"T{}\" }}\n",
+                i,
+                i + 1,
+                i + 1
+            );
+        }
+        source += &format!("thing \"T{}\" {{ x = 1 }}\n", count);
+        let parser = populate_parser(&source).unwrap();
+        assert_eq!(
+            parser.things.get("T0").unwrap().props["x"].value,
+            PropValue::Int(1)
         );
     }
 }
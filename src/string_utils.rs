@@ -9,6 +9,107 @@ pub fn strip_quotes(val: &str) -> &str {
     return chars.as_str();
 }
 
+/// Strips the leading `///` and the single space that conventionally follows it.
+pub fn strip_doc_comment(val: &str) -> &str {
+    let text = val.strip_prefix("///").unwrap_or(val);
+    return text.strip_prefix(' ').unwrap_or(text);
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Decodes pairs of hex digits, in either case.
+pub fn decode_hex(val: &str) -> Result<Vec<u8>, String> {
+    if !val.len().is_multiple_of(2) {
+        return Err("Hex literal has an odd number of digits".to_string());
+    }
+
+    let digit = |c: u8| match (c as char).to_digit(16) {
+        Some(digit) => Ok(digit as u8),
+        None => Err("Invalid hex digit `".to_owned() + &(c as char).to_string() + "`"),
+    };
+    return val
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| Ok(digit(pair[0])? << 4 | digit(pair[1])?))
+        .collect();
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/// Decodes standard, padded base64.
+pub fn decode_base64(val: &str) -> Result<Vec<u8>, String> {
+    if !val.len().is_multiple_of(4) {
+        return Err("Base64 literal length must be a multiple of 4".to_string());
+    }
+
+    let data = val.trim_end_matches('=');
+    if val.len() - data.len() > 2 {
+        return Err("Base64 literal has too much padding".to_string());
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    for (i, c) in data.chars().enumerate() {
+        let Some(sextet) = BASE64_ALPHABET.iter().position(|a| *a as char == c) else {
+            return Err("Invalid base64 character `".to_owned() + &c.to_string() + "`");
+        };
+        buffer = buffer << 6 | sextet as u32;
+        if i % 4 == 3 {
+            bytes.extend_from_slice(&buffer.to_be_bytes()[1..]);
+            buffer = 0;
+        }
+    }
+
+    // leftover characters of a padded group, their unused low bits have to be zero
+    match data.len() % 4 {
+        2 if buffer & 0xf == 0 => bytes.push((buffer >> 4) as u8),
+        3 if buffer & 0x3 == 0 => bytes.extend_from_slice(&((buffer >> 2) as u16).to_be_bytes()),
+        0 => {}
+        _ => return Err("Base64 literal is not canonically padded".to_string()),
+    }
+    return Ok(bytes);
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TemplatePart<'a> {
+    Text(&'a str),
+    Name(&'a str),
+}
+
+/// Splits a string into literal text and `${name}` placeholders. `$${` is an escaped, literal `${`.
+pub fn split_template(val: &str) -> Result<Vec<TemplatePart<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = val;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            parts.push(TemplatePart::Text(&rest[..start - 1]));
+            parts.push(TemplatePart::Text(&rest[start..start + 2]));
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        parts.push(TemplatePart::Text(&rest[..start]));
+        let Some(end) = rest[start..].find('}') else {
+            return Err("Unterminated `${` in string interpolation".to_string());
+        };
+
+        let name = rest[start + 2..start + end].trim();
+        if name.is_empty() {
+            return Err("Expected name inside `${}` in string interpolation".to_string());
+        }
+
+        parts.push(TemplatePart::Name(name));
+        rest = &rest[start + end + 1..];
+    }
+
+    parts.push(TemplatePart::Text(rest));
+    parts.retain(|part| *part != TemplatePart::Text(""));
+    return Ok(parts);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -22,4 +123,65 @@ mod tests {
     fn unquoted_strings_remain_the_same() {
         assert_eq!(strip_quotes("hello"), "hello");
     }
+
+    #[test]
+    fn strip_doc_comment_strips_slashes_and_one_space() {
+        assert_eq!(strip_doc_comment("/// hello"), "hello");
+        assert_eq!(strip_doc_comment("///   indented"), "  indented");
+        assert_eq!(strip_doc_comment("///"), "");
+    }
+
+    #[test]
+    fn split_template_finds_placeholders() {
+        assert_eq!(
+            split_template("${root}/assets/${ name }.png").unwrap(),
+            vec![
+                TemplatePart::Name("root"),
+                TemplatePart::Text("/assets/"),
+                TemplatePart::Name("name"),
+                TemplatePart::Text(".png"),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_template_keeps_escaped_placeholders_as_text() {
+        assert_eq!(
+            split_template("cost: $${price}").unwrap(),
+            vec![
+                TemplatePart::Text("cost: "),
+                TemplatePart::Text("${"),
+                TemplatePart::Text("price}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_template_rejects_malformed_placeholders() {
+        assert!(split_template("${root").is_err());
+        assert!(split_template("${ }").is_err());
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(decode_hex("DEADbeef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(encode_hex(&[0xde, 0xad, 0x0f]), "dead0f");
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn base64_decodes_padded_groups() {
+        for (text, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v")] {
+            assert_eq!(decode_base64(encoded), Ok(text.as_bytes().to_vec()));
+        }
+        assert_eq!(
+            decode_base64("aGVsbG8gd29ybGQ="),
+            Ok(b"hello world".to_vec())
+        );
+        assert!(decode_base64("Zg=").is_err());
+        assert!(decode_base64("Zh==").is_err());
+        assert!(decode_base64("Z===").is_err());
+        assert!(decode_base64("Zm9*").is_err());
+    }
 }
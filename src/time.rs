@@ -0,0 +1,432 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A calendar date, e.g. `2026-10-17`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// A point in time in UTC, e.g. `2026-10-17T12:00:00Z`. Offsets are applied while parsing.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct DateTime {
+    pub date: Date,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> Option<Self> {
+        if month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        return Some(Self {
+            year: year,
+            month: month,
+            day: day,
+        });
+    }
+
+    /// Parses `2026-10-17` or the compact `20261017`.
+    pub fn from_literal(literal: &str) -> Option<Self> {
+        if !literal.is_ascii() {
+            return None;
+        }
+        let (year, month, day) = match literal.len() {
+            10 if &literal[4..5] == "-" && &literal[7..8] == "-" => {
+                (&literal[0..4], &literal[5..7], &literal[8..10])
+            }
+            8 => (&literal[0..4], &literal[4..6], &literal[6..8]),
+            _ => return None,
+        };
+        return Self::new(
+            parse_digits(year)? as i32,
+            parse_digits(month)? as u8,
+            parse_digits(day)? as u8,
+        );
+    }
+
+    /// Days since `1970-01-01`.
+    fn days_since_epoch(&self) -> i64 {
+        // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        return era * 146097 + day_of_era - 719468;
+    }
+
+    fn from_days_since_epoch(days: i64) -> Self {
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        return Self {
+            year: year as i32,
+            month: month as u8,
+            day: day as u8,
+        };
+    }
+}
+
+impl DateTime {
+    /// Parses `2026-10-17T12:00:00Z` with optional fractional seconds and a `Z` or `+hh:mm`
+    /// offset, or the compact `20261017T120000Z`. Seconds may be left out.
+    pub fn from_literal(literal: &str) -> Option<Self> {
+        if !literal.is_ascii() {
+            return None;
+        }
+        let (date, time) = literal.split_once('T')?;
+        let date = Date::from_literal(date)?;
+
+        let (time, offset_seconds) = match time.find(['Z', '+', '-']) {
+            Some(index) => (&time[..index], parse_offset(&time[index..])?),
+            None => return None,
+        };
+
+        let (time, nanosecond) = match time.split_once('.') {
+            Some((time, fraction)) => (time, parse_fraction(fraction)?),
+            None => (time, 0),
+        };
+
+        let fields: Vec<&str> = if time.contains(':') {
+            time.split(':').collect()
+        } else if time.len() % 2 == 0 {
+            (0..time.len())
+                .step_by(2)
+                .map(|i| &time[i..i + 2])
+                .collect()
+        } else {
+            return None;
+        };
+        if fields.len() < 2 || fields.len() > 3 || fields.iter().any(|field| field.len() != 2) {
+            return None;
+        }
+
+        let hour = parse_digits(fields[0])?;
+        let minute = parse_digits(fields[1])?;
+        let second = match fields.get(2) {
+            Some(second) => parse_digits(second)?,
+            None => 0,
+        };
+        if hour > 23 || minute > 59 || second > 59 || (nanosecond > 0 && fields.len() < 3) {
+            return None;
+        }
+
+        let seconds = date.days_since_epoch() * SECONDS_PER_DAY
+            + (hour * 3600 + minute * 60 + second) as i64
+            - offset_seconds;
+        return Some(Self::from_unix(seconds, nanosecond));
+    }
+
+    fn from_unix(seconds: i64, nanosecond: u32) -> Self {
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+        return Self {
+            date: Date::from_days_since_epoch(seconds.div_euclid(SECONDS_PER_DAY)),
+            hour: (time / 3600) as u8,
+            minute: (time % 3600 / 60) as u8,
+            second: (time % 60) as u8,
+            nanosecond: nanosecond,
+        };
+    }
+
+    /// Whole seconds since `1970-01-01T00:00:00Z`, negative for earlier points in time.
+    pub fn unix_seconds(&self) -> i64 {
+        return self.date.days_since_epoch() * SECONDS_PER_DAY
+            + (self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64);
+    }
+
+    pub fn to_system_time(&self) -> SystemTime {
+        let seconds = self.unix_seconds();
+        let nanos = Duration::from_nanos(self.nanosecond as u64);
+        if seconds >= 0 {
+            return UNIX_EPOCH + Duration::from_secs(seconds as u64) + nanos;
+        }
+        return UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs()) + nanos;
+    }
+}
+
+/// Parses an ISO 8601 duration like `PT1H30M` or `P1DT0.5S`, or the compact form `1h30m` with
+/// the units `d`, `h`, `m`, `s`, `ms`, `us` and `ns`. Years and months are rejected since their
+/// length varies.
+pub fn parse_duration(literal: &str) -> Option<Duration> {
+    if let Some(iso) = literal.strip_prefix('P') {
+        return parse_iso_duration(iso);
+    }
+
+    let mut rest = literal;
+    let mut total = Duration::ZERO;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let value = parse_digits(&rest[..digits])? as u64;
+        rest = &rest[digits..];
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let component = match &rest[..unit] {
+            "d" => Duration::from_secs(value.checked_mul(SECONDS_PER_DAY as u64)?),
+            "h" => Duration::from_secs(value.checked_mul(3600)?),
+            "m" => Duration::from_secs(value.checked_mul(60)?),
+            "s" => Duration::from_secs(value),
+            "ms" => Duration::from_millis(value),
+            "us" => Duration::from_micros(value),
+            "ns" => Duration::from_nanos(value),
+            _ => return None,
+        };
+        rest = &rest[unit..];
+        total = total.checked_add(component)?;
+    }
+    return Some(total);
+}
+
+fn parse_iso_duration(literal: &str) -> Option<Duration> {
+    let (date, time) = match literal.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, time),
+        Some(_) => return None,
+        None => (literal, ""),
+    };
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+
+    let mut total = Duration::ZERO;
+    for (part, units) in [(date, "WD"), (time, "HMS")] {
+        let mut rest = part;
+        let mut allowed = units;
+        while !rest.is_empty() {
+            let end = rest.find(|c: char| c.is_ascii_alphabetic())?;
+            let unit = rest[end..].chars().next().unwrap();
+            // units have to appear in order and at most once
+            let unit_index = allowed.find(unit)?;
+            allowed = &allowed[unit_index + 1..];
+
+            let number = &rest[..end];
+            rest = &rest[end + 1..];
+            let component = match (unit, number.split_once('.')) {
+                ('S', Some((seconds, fraction))) => {
+                    Duration::new(parse_digits(seconds)? as u64, parse_fraction(fraction)?)
+                }
+                (_, Some(_)) => return None,
+                (_, None) => {
+                    let value = parse_digits(number)? as u64;
+                    let seconds = match unit {
+                        'W' => 7 * SECONDS_PER_DAY as u64,
+                        'D' => SECONDS_PER_DAY as u64,
+                        'H' => 3600,
+                        'M' => 60,
+                        _ => 1,
+                    };
+                    Duration::from_secs(value.checked_mul(seconds)?)
+                }
+            };
+            total = total.checked_add(component)?;
+        }
+    }
+    return Some(total);
+}
+
+/// Formats a duration as ISO 8601, e.g. `P1DT2H30M` or `PT0.25S`.
+pub fn format_duration(duration: &Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours) = (seconds / SECONDS_PER_DAY as u64, seconds / 3600 % 24);
+    let (minutes, seconds) = (seconds / 60 % 60, seconds % 60);
+
+    let mut literal = "P".to_string();
+    if days > 0 {
+        literal += &(days.to_string() + "D");
+    }
+    if hours == 0 && minutes == 0 && seconds == 0 && duration.subsec_nanos() == 0 {
+        return if days > 0 {
+            literal
+        } else {
+            "PT0S".to_string()
+        };
+    }
+
+    literal.push('T');
+    if hours > 0 {
+        literal += &(hours.to_string() + "H");
+    }
+    if minutes > 0 {
+        literal += &(minutes.to_string() + "M");
+    }
+    if seconds > 0 || duration.subsec_nanos() > 0 {
+        literal += &(seconds.to_string() + &format_fraction(duration.subsec_nanos()) + "S");
+    }
+    return literal;
+}
+
+fn format_fraction(nanosecond: u32) -> String {
+    if nanosecond == 0 {
+        return String::new();
+    }
+    return ".".to_owned() + format!("{:09}", nanosecond).trim_end_matches('0');
+}
+
+fn parse_digits(literal: &str) -> Option<u32> {
+    if literal.is_empty() || !literal.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    return literal.parse().ok();
+}
+
+/// Parses the digits after a decimal point as nanoseconds.
+fn parse_fraction(literal: &str) -> Option<u32> {
+    if literal.len() > 9 {
+        return None;
+    }
+    return Some(parse_digits(literal)? * 10u32.pow(9 - literal.len() as u32));
+}
+
+/// Parses `Z`, `±hh:mm` or `±hhmm` into seconds east of UTC.
+fn parse_offset(literal: &str) -> Option<i64> {
+    if literal == "Z" {
+        return Some(0);
+    }
+
+    let (sign, offset) = match literal.split_at_checked(1)? {
+        ("+", offset) => (1, offset),
+        ("-", offset) => (-1, offset),
+        _ => return None,
+    };
+    let (hours, minutes) = match offset.len() {
+        5 if &offset[2..3] == ":" => (&offset[..2], &offset[3..]),
+        4 => (&offset[..2], &offset[2..]),
+        _ => return None,
+    };
+    let (hours, minutes) = (parse_digits(hours)?, parse_digits(minutes)?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    return Some(sign * (hours * 3600 + minutes * 60) as i64);
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    return match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day);
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "{}T{:02}:{:02}:{:02}{}Z",
+            self.date,
+            self.hour,
+            self.minute,
+            self.second,
+            format_fraction(self.nanosecond)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_parse_from_iso_and_compact_forms() {
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(
+            parse_duration("P1DT0.5S"),
+            Some(Duration::new(86400, 500_000_000))
+        );
+        assert_eq!(parse_duration("P1Y"), None);
+        assert_eq!(parse_duration("PT30M1H"), None);
+        assert_eq!(parse_duration("1x"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn durations_format_as_iso() {
+        assert_eq!(format_duration(&Duration::from_secs(5400)), "PT1H30M");
+        assert_eq!(format_duration(&Duration::from_secs(86400)), "P1D");
+        assert_eq!(format_duration(&Duration::from_millis(250)), "PT0.25S");
+        assert_eq!(format_duration(&Duration::ZERO), "PT0S");
+    }
+
+    #[test]
+    fn dates_are_validated() {
+        assert_eq!(Date::from_literal("2024-02-29"), Date::new(2024, 2, 29));
+        assert_eq!(
+            Date::from_literal("20261017").unwrap().to_string(),
+            "2026-10-17"
+        );
+        assert_eq!(Date::from_literal("2026-02-29"), None);
+        assert_eq!(Date::from_literal("2026-13-01"), None);
+    }
+
+    #[test]
+    fn datetimes_are_normalized_to_utc() {
+        let datetime = DateTime::from_literal("2026-10-17T12:00:00Z").unwrap();
+        assert_eq!(datetime.to_string(), "2026-10-17T12:00:00Z");
+        assert_eq!(datetime.unix_seconds(), 1_792_238_400);
+
+        let datetime = DateTime::from_literal("2026-01-01T01:30:00.5+02:00").unwrap();
+        assert_eq!(datetime.to_string(), "2025-12-31T23:30:00.5Z");
+        assert_eq!(
+            DateTime::from_literal("20261017T1200Z"),
+            DateTime::from_literal("2026-10-17T12:00Z")
+        );
+        assert_eq!(DateTime::from_literal("2026-10-17T12:00:00"), None);
+        assert_eq!(DateTime::from_literal("2026-10-17T24:00:00Z"), None);
+    }
+
+    #[test]
+    fn malformed_offsets_are_rejected() {
+        assert_eq!(parse_offset("+05:30"), Some(5 * 3600 + 30 * 60));
+        assert_eq!(parse_offset("-0530"), Some(-(5 * 3600 + 30 * 60)));
+        for offset in [
+            "+:0000", "+00::00", "+0:000", "+000:0", "+00:0", "05:30", "+",
+        ] {
+            assert_eq!(parse_offset(offset), None, "{}", offset);
+        }
+        assert_eq!(DateTime::from_literal("2026-10-17T12:00:00+:0000"), None);
+    }
+
+    #[test]
+    fn datetimes_convert_to_system_time() {
+        let datetime = DateTime::from_literal("1969-12-31T23:59:59Z").unwrap();
+        assert_eq!(
+            datetime.to_system_time(),
+            UNIX_EPOCH - Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::core::PropValue;
+
+/// Turns the string a custom prop is assigned into the fields of its `PropValue::Custom`, or an
+/// error message when the string isn't a valid value of the type.
+pub type ParseLiteral = dyn Fn(&str) -> Result<Vec<PropValue>, String>;
+
+/// Prop types registered in addition to the built-in ones, keyed by their keyword.
+#[derive(Clone, Default)]
+pub struct PropTypes {
+    types: HashMap<String, Rc<ParseLiteral>>,
+}
+
+impl PropTypes {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        parse: impl Fn(&str) -> Result<Vec<PropValue>, String> + 'static,
+    ) {
+        self.types.insert(name.into(), Rc::new(parse));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        return self.types.contains_key(name);
+    }
+
+    /// Parses `literal` as a value of the type `name`, `None` if no such type is registered.
+    pub fn parse(&self, name: &str, literal: &str) -> Option<Result<PropValue, String>> {
+        let parse = self.types.get(name)?;
+        return Some(parse(literal).map(|fields| PropValue::Custom {
+            ty: name.to_string(),
+            fields: fields,
+        }));
+    }
+}
+
+impl fmt::Debug for PropTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.debug_set().entries(self.types.keys()).finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_types_wrap_their_fields() {
+        let mut types = PropTypes::new();
+        types.register("upper", |literal| {
+            return Ok(vec![PropValue::String(literal.to_uppercase())]);
+        });
+
+        assert!(types.contains("upper"));
+        assert_eq!(
+            types.parse("upper", "abc"),
+            Some(Ok(PropValue::Custom {
+                ty: "upper".to_string(),
+                fields: vec![PropValue::String("ABC".to_string())],
+            }))
+        );
+        assert_eq!(types.parse("lower", "abc"), None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+/// What a unit measures. Values can only be added, subtracted and compared within a dimension.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Dimension {
+    Length,
+    Time,
+    Angle,
+    Data,
+}
+
+/// A unit suffix of numeric literals, e.g. the `ms` in `250ms`.
+#[derive(PartialEq, Debug)]
+pub struct Unit {
+    pub name: &'static str,
+    pub dimension: Dimension,
+    /// Size of the unit in the canonical unit of its dimension.
+    pub factor: f64,
+}
+
+const fn unit(name: &'static str, dimension: Dimension, factor: f64) -> Unit {
+    return Unit {
+        name: name,
+        dimension: dimension,
+        factor: factor,
+    };
+}
+
+/// Every known unit, the first of each dimension is its canonical unit.
+const UNITS: [Unit; 19] = [
+    unit("m", Dimension::Length, 1.0),
+    unit("mm", Dimension::Length, 0.001),
+    unit("cm", Dimension::Length, 0.01),
+    unit("km", Dimension::Length, 1000.0),
+    unit("s", Dimension::Time, 1.0),
+    unit("ns", Dimension::Time, 1e-9),
+    unit("us", Dimension::Time, 1e-6),
+    unit("ms", Dimension::Time, 0.001),
+    unit("min", Dimension::Time, 60.0),
+    unit("h", Dimension::Time, 3600.0),
+    unit("rad", Dimension::Angle, 1.0),
+    unit("deg", Dimension::Angle, PI / 180.0),
+    unit("B", Dimension::Data, 1.0),
+    unit("KB", Dimension::Data, 1e3),
+    unit("MB", Dimension::Data, 1e6),
+    unit("GB", Dimension::Data, 1e9),
+    unit("KiB", Dimension::Data, 1024.0),
+    unit("MiB", Dimension::Data, 1024.0 * 1024.0),
+    unit("GiB", Dimension::Data, 1024.0 * 1024.0 * 1024.0),
+];
+
+impl Dimension {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Dimension::Length => "length",
+            Dimension::Time => "time",
+            Dimension::Angle => "angle",
+            Dimension::Data => "data size",
+        };
+    }
+
+    /// `m`, `s`, `rad` or `B`, the unit values of this dimension are stored in.
+    pub fn canonical(&self) -> &'static Unit {
+        return UNITS.iter().find(|unit| unit.dimension == *self).unwrap();
+    }
+}
+
+impl Unit {
+    /// Looks up a unit by its case-sensitive name.
+    pub fn lookup(name: &str) -> Option<&'static Unit> {
+        return UNITS.iter().find(|unit| unit.name == name);
+    }
+
+    pub fn to_canonical(&self, value: f64) -> f64 {
+        return value * self.factor;
+    }
+
+    pub fn from_canonical(&self, value: f64) -> f64 {
+        return value / self.factor;
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(self.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_convert_to_and_from_canonical() {
+        let ms = Unit::lookup("ms").unwrap();
+        assert_eq!(ms.dimension, Dimension::Time);
+        assert_eq!(ms.to_canonical(250.0), 0.25);
+        assert_eq!(ms.from_canonical(0.25), 250.0);
+        assert_eq!(Unit::lookup("KiB").unwrap().to_canonical(2.0), 2048.0);
+        assert_eq!(Unit::lookup("kib"), None);
+    }
+
+    #[test]
+    fn each_dimension_has_a_canonical_unit() {
+        assert_eq!(Dimension::Length.canonical().name, "m");
+        assert_eq!(Dimension::Time.canonical().name, "s");
+        assert_eq!(Dimension::Angle.canonical().name, "rad");
+        assert_eq!(Dimension::Data.canonical().name, "B");
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Vectors are written as parenthesized components, e.g. `(1.0, 2.0, 3.0)` for a [`Vec3`].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Vec2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Vec4 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Vec2 {
+    pub fn new(x: f64, y: f64) -> Self {
+        return Self { x: x, y: y };
+    }
+}
+
+impl Vec3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        return Self { x: x, y: y, z: z };
+    }
+}
+
+impl Vec4 {
+    pub fn new(x: f64, y: f64, z: f64, w: f64) -> Self {
+        return Self {
+            x: x,
+            y: y,
+            z: z,
+            w: w,
+        };
+    }
+}
+
+impl fmt::Display for Vec2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "({}, {})", self.x, self.y);
+    }
+}
+
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "({}, {}, {})", self.x, self.y, self.z);
+    }
+}
+
+impl fmt::Display for Vec4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "({}, {}, {}, {})", self.x, self.y, self.z, self.w);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+/// Language version declared by a leading `fdl <major>.<minor>` directive.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// Newest language version this parser understands.
+pub const CURRENT: Version = Version { major: 1, minor: 3 };
+
+/// Syntax introduced after `1.0`, only accepted from files declaring at least its version.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Feature {
+    UntypedProps,
+    WideNumbers,
+    Enums,
+    Refs,
+    Constants,
+    Expressions,
+    Interpolation,
+    Environment,
+    Inheritance,
+    Kinds,
+    AnonymousThings,
+    Annotations,
+    DocComments,
+    Conditionals,
+    Units,
+    TimeTypes,
+    Bytes,
+    Colors,
+    Vectors,
+    Patches,
+    CustomTypes,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32) -> Self {
+        return Self {
+            major: major,
+            minor: minor,
+        };
+    }
+
+    /// Parses `1` or `1.2`.
+    pub fn from_literal(literal: &str) -> Option<Self> {
+        let (major, minor) = literal.split_once('.').unwrap_or((literal, "0"));
+        return Some(Self::new(major.parse().ok()?, minor.parse().ok()?));
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}.{}", self.major, self.minor);
+    }
+}
+
+impl Feature {
+    pub fn since(&self) -> Version {
+        return match self {
+            Feature::UntypedProps
+            | Feature::WideNumbers
+            | Feature::Enums
+            | Feature::Refs
+            | Feature::Constants
+            | Feature::Expressions
+            | Feature::Interpolation
+            | Feature::Environment => Version::new(1, 1),
+            Feature::Inheritance
+            | Feature::Kinds
+            | Feature::AnonymousThings
+            | Feature::Annotations
+            | Feature::DocComments
+            | Feature::Conditionals => Version::new(1, 2),
+            Feature::Units
+            | Feature::TimeTypes
+            | Feature::Bytes
+            | Feature::Colors
+            | Feature::Vectors
+            | Feature::Patches
+            | Feature::CustomTypes => Version::new(1, 3),
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Feature::UntypedProps => "untyped props",
+            Feature::WideNumbers => "long, uint and double types",
+            Feature::Enums => "enums",
+            Feature::Refs => "refs",
+            Feature::Constants => "constants",
+            Feature::Expressions => "expressions",
+            Feature::Interpolation => "string interpolation",
+            Feature::Environment => "environment variables",
+            Feature::Inheritance => "inheritance",
+            Feature::Kinds => "thing kinds",
+            Feature::AnonymousThings => "anonymous things",
+            Feature::Annotations => "annotations",
+            Feature::DocComments => "doc comments",
+            Feature::Conditionals => "conditional blocks",
+            Feature::Units => "units",
+            Feature::TimeTypes => "duration, date and datetime types",
+            Feature::Bytes => "bytes",
+            Feature::Colors => "colors",
+            Feature::Vectors => "vectors",
+            Feature::Patches => "patches",
+            Feature::CustomTypes => "custom prop types",
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_parse_from_literals() {
+        assert_eq!(Version::from_literal("1.2"), Some(Version::new(1, 2)));
+        assert_eq!(Version::from_literal("2"), Some(Version::new(2, 0)));
+        assert_eq!(Version::from_literal("1.x"), None);
+        assert_eq!(Version::new(1, 10).to_string(), "1.10");
+    }
+
+    #[test]
+    fn versions_compare_by_major_then_minor() {
+        assert!(Version::new(1, 10) > Version::new(1, 2));
+        assert!(Version::new(2, 0) > Version::new(1, 10));
+        assert!(Feature::Conditionals.since() <= CURRENT);
+        assert!(Feature::Units.since() <= CURRENT);
+    }
+}
@@ -76,7 +76,7 @@ fn parse_a_complex_source() {
     assert_eq!(parser.things.len(), 1);
     let thing = parser.things.get("Thing Name").unwrap();
     assert_eq!(thing.name, "Thing Name");
-    assert_eq!(thing.things.len(), 0);
+    assert_eq!(thing.num_things(), 0);
     assert_eq!(thing.props.len(), 4);
 
     let int_prop = thing.props.get("int_prop").unwrap();
@@ -121,28 +121,91 @@ fn parse_nested_things_with_props() {
     assert_eq!(parser.things.len(), 1);
     let thing = parser.things.get("Root").unwrap();
     assert_eq!(thing.name, "Root");
-    assert_eq!(thing.things.len(), 2);
+    assert_eq!(thing.num_things(), 2);
     assert_eq!(thing.props.len(), 1);
 
     let int_prop = thing.props.get("int_prop").unwrap();
     assert_eq!(int_prop.name, "int_prop");
     assert_eq!(int_prop.value, PropValue::Int(12));
 
-    let child1 = thing.things.get("Child1").unwrap();
+    let child1 = thing.get_thing("Child1").unwrap();
     assert_eq!(child1.name, "Child1");
-    assert_eq!(child1.things.len(), 0);
+    assert_eq!(child1.num_things(), 0);
     assert_eq!(child1.props.len(), 1);
 
     let bool_prop = child1.props.get("bool_prop").unwrap();
     assert_eq!(bool_prop.name, "bool_prop");
     assert_eq!(bool_prop.value, PropValue::Bool(true));
 
-    let child2 = thing.things.get("Child2").unwrap();
+    let child2 = thing.get_thing("Child2").unwrap();
     assert_eq!(child2.name, "Child2");
-    assert_eq!(child2.things.len(), 0);
+    assert_eq!(child2.num_things(), 0);
     assert_eq!(child2.props.len(), 1);
 
     let float_prop = child2.props.get("float_prop").unwrap();
     assert_eq!(float_prop.name, "float_prop");
     assert_eq!(float_prop.value, PropValue::Float(12.1));
 }
+
+#[test]
+fn parse_the_sample_file() {
+    let document = fdl::parse_file("resources/sample.fdl").unwrap();
+    assert_eq!(document.file_name.as_deref(), Some("resources/sample.fdl"));
+    assert!(!document.things.is_empty());
+
+    let bye = document
+        .things
+        .get("Hello")
+        .unwrap()
+        .get_thing("Bye")
+        .unwrap();
+    assert_eq!(bye.props["z"].value, PropValue::Float(9000.01));
+}
+
+#[test]
+fn parse_the_colors_and_vectors_sample() {
+    let document = fdl::parse_file("resources/colors_and_vectors.fdl").unwrap();
+    let marker = document
+        .things
+        .get("Palette")
+        .unwrap()
+        .get_thing("Marker")
+        .unwrap();
+    assert_eq!(marker.get_vec3("pos").unwrap().z, 9000.01);
+    assert_eq!(marker.get_vec2("size").unwrap().x, 4.0);
+    assert_eq!(marker.get_color("tint").unwrap().g, 0x88);
+    assert_eq!(marker.get_color("shadow").unwrap().a, 128);
+}
+
+#[test]
+fn repeated_anonymous_children_are_reachable_in_order() {
+    let source = r#"
+        thing "Route" {
+            waypoint { int x = 1 }
+            waypoint { int x = 2 }
+            thing "End" { }
+        }
+    "#;
+    let mut parser = Parser::build()
+        .block_keyword("waypoint")
+        .parse(Lexer::new(source))
+        .unwrap();
+
+    let route = parser.things.get("Route").unwrap();
+    let children = route.things();
+    assert_eq!(children.len(), 3);
+    assert!(children.at(0).unwrap().is_kind("waypoint"));
+    assert_eq!(children.at(1).unwrap().props["x"].value, PropValue::Int(2));
+    assert_eq!(children.at(2).unwrap().name, "End");
+
+    let xs: Vec<&PropValue> = children
+        .iter()
+        .filter(|thing| thing.name.is_empty())
+        .map(|thing| &thing.props["x"].value)
+        .collect();
+    assert_eq!(xs, [&PropValue::Int(1), &PropValue::Int(2)]);
+
+    let route = parser.things.get_mut("Route").unwrap();
+    route.things_mut().at_mut(0).unwrap().name = "Start".to_string();
+    assert!(route.get_thing("Start").is_some());
+}